@@ -7,6 +7,12 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! The USB identity (`usb_id`) is taken from the environment, e.g.,
+//! `USB_PID=0x0001 USB_PRODUCT="Test mouse" cargo build ..`:
+//!
+//! - `USB_VID`, `USB_PID`, `USB_BCD_DEVICE`, decimal or 0x hex
+//! - `USB_MANUFACTURER`, `USB_PRODUCT`
 
 use core::f64::consts::PI;
 use std::env;
@@ -16,6 +22,11 @@ use std::{
     path::{Path, PathBuf},
 };
 
+// The id parsing and defaults, shared with the host tools
+#[path = "src/usb_id/env.rs"]
+mod usb_env;
+use usb_env::parse_u16;
+
 fn main() -> Result<()> {
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
@@ -50,5 +61,37 @@ fn main() -> Result<()> {
     }
     write!(f, "];\n")?;
 
+    usb_id(&Path::new(&out_dir).join("usb_id.rs"))?;
+
+    Ok(())
+}
+
+// Writes the USB identity, from the environment or the defaults
+fn usb_id(path: &Path) -> Result<()> {
+    let mut f = File::create(path)?;
+    for (name, var, default) in [
+        ("VID", "USB_VID", usb_env::VID),
+        ("PID", "USB_PID", usb_env::PID),
+        ("BCD_DEVICE", "USB_BCD_DEVICE", usb_env::BCD_DEVICE),
+    ]
+    .iter()
+    {
+        println!("cargo:rerun-if-env-changed={}", var);
+        let value = match env::var(var) {
+            Ok(s) => parse_u16(&s).unwrap_or_else(|| panic!("{}={} is not a u16", var, s)),
+            Err(_) => *default,
+        };
+        writeln!(f, "pub const {}: u16 = 0x{:04x};", name, value)?;
+    }
+    for (name, var, default) in [
+        ("MANUFACTURER", "USB_MANUFACTURER", "Mouse company"),
+        ("PRODUCT", "USB_PRODUCT", "Mouse"),
+    ]
+    .iter()
+    {
+        println!("cargo:rerun-if-env-changed={}", var);
+        let value = env::var(var).unwrap_or_else(|_| default.to_string());
+        writeln!(f, "pub const {}: &str = {:?};", name, value)?;
+    }
     Ok(())
 }
@@ -10,7 +10,7 @@
 use embedded_hal::spi::MODE_3;
 use panic_rtt_target as _;
 use rtt_target as _;
-use cortex_m::{asm::delay, peripheral::DWT};
+use cortex_m::{asm::{delay, wfi}, interrupt, peripheral::DWT};
 use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
 use stm32f4xx_hal::{
     gpio,
@@ -18,47 +18,34 @@ use stm32f4xx_hal::{
     prelude::*,
 };
 use usb_device::{bus::UsbBusAllocator, prelude::*};
-use usbd_hid::{
-    descriptor::{generator_prelude::*, MouseReport},
-    hid_class::HIDClass
+use app::{
+    buttons::Action,
+    cdc_acm::{self, SerialClass},
+    config_proto::{self, Command, Config, Diagnostics, LedMode, Response, Status},
+    console::{Console, Input},
+    dfu::{self, DfuRuntimeClass},
+    encoder::Quadrature,
+    flash::ConfigFlash,
+    hid::MouseClass,
+    keyboard::{self, KeyboardClass},
+    low_power::{self, StopMode, WakeupTimer},
+    pmw3389::{self, Register},
+    pmw3389e,
+    report_queue::ReportQueue,
+    report_stats::ReportStats,
+    scaler::{self, MotionScaler},
+    scroll::Scroll,
+    suspend::{self, Event, Suspend},
+    usb_id,
+    DwtDelay,
 };
-use app::{DwtDelay, pmw3389::{self, Register}, pmw3389e};
-use usbd_hid::descriptor::SerializedDescriptor;
-use usbd_hid::descriptor::AsInputReport;
-use usbd_hid::descriptor::gen_hid_descriptor;
-#[gen_hid_descriptor(
-    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = MOUSE) = {
-        (collection = PHYSICAL, usage = POINTER) = {
-            (usage_page = BUTTON, usage_min = 0x01, usage_max = 0x05) = {
-                #[packed_bits 5] #[item_settings data,variable,absolute] buttons=input;
-            };
-            (usage_page = GENERIC_DESKTOP,) = {
-                (usage = X,) = {
-                    #[item_settings data,variable,relative] x=input;
-                };
-                (usage = Y,) = {
-                    #[item_settings data,variable,relative] y=input;
-                };
-                (usage = WHEEL,) = {
-                    #[item_settings data,variable,relative] wheel=input;
-                };
-            };
-        };
-    }
-)]
-pub struct PMouseReport {
-    pub buttons: u8,
-    pub x: i8,
-    pub y: i8,
-    pub wheel: i8, // Scroll down (negative) or up (positive) this many units
-}
 
 use rtic::cyccnt::{Instant, U32Ext as _};
 use stm32f4xx_hal::{
     dwt::Dwt,
     gpio::Speed,
     gpio::{
-        gpiob::{PB10, PB4},
+        gpiob::{PB10, PB4, PB6, PB7},
         gpioc::{PC2, PC3},
         Alternate, Output, PushPull,
     },
@@ -91,15 +78,68 @@ use stm32f4xx_hal::{
     prelude::*,
 };
 
-const OFFSET: u32 = 1_000_000;
+// Period of toggle_speed, a whole number of ms for the HID idle timers
+const OFFSET_MS: u32 = 20;
+const OFFSET: u32 = OFFSET_MS * SAMPLE;
+// Sensor sample period, 1 ms at 48 MHz
+const SAMPLE: u32 = 48_000;
+// Sample period while suspended, by the RTC wakeup timer, also the resume
+// signalling time
+const SUSPEND_SAMPLE_MS: u32 = suspend::RESUME_MS;
+// Time for the status stage of DFU_DETACH before the reset, 5 ms
+const DETACH_DELAY: u32 = 5 * 48_000;
+
+// Where a `Save` was requested, answered by `save` when done
+#[derive(Clone, Copy)]
+enum Origin {
+    Feature,
+    Console,
+}
+
+// The ROM bootloader, if requested over DFU before the reset
+#[cortex_m_rt::pre_init]
+unsafe fn pre_init() {
+    dfu::boot_if_requested();
+}
+// Scaler step, 0.1 in Q16.16
+const SCALE_STEP: u32 = scaler::ONE / 10;
+// Scaled sensor counts per wheel detent, when scrolling by motion
+const SCROLL_COUNTS: u16 = 40;
+// The keyboard interface, replaced by the serial console with the `console`
+// feature, buttons are then not mapped to keys
+const KEYBOARD: bool = cfg!(not(feature = "console"));
+// Wheel encoder, transitions per detent (full cycle) and debounce samples
+const WHEEL_TRANSITIONS: u8 = 4;
+const WHEEL_DEBOUNCE: u8 = 2;
 
 #[rtic::app(device = stm32f4xx_hal::stm32, monotonic = rtic::cyccnt::CYCCNT, peripherals = true)]
 const APP: () = {
     struct Resources {
         // late resources
-        hid: HIDClass<'static, UsbBusType>,
+        hid: MouseClass<'static, UsbBusType>,
+        queue: ReportQueue,
+        stats: ReportStats,
+        scroll: Scroll,
+        wheel: Quadrature,
+        wheel_a: PB6<Input<PullUp>>,
+        wheel_b: PB7<Input<PullUp>>,
+        suspend: Suspend,
+        // Stop mode while suspended, woken by the RTC to sample
+        stop_mode: StopMode,
+        wakeup: WakeupTimer,
+        // the keyboard, or with the console feature the serial port, as the
+        // endpoints (3 IN) don't suffice for both
+        kbd: Option<KeyboardClass<'static, UsbBusType>>,
+        serial: Option<SerialClass<'static, UsbBusType>>,
+        dfu: DfuRuntimeClass,
+        console: Console,
+        config: Config,
+        flash: ConfigFlash,
         usb_dev: UsbDevice<'static, UsbBusType>,
-        pmw3389: PMW3389T,
+        // taken by `reinit` while the sensor is re-initialized
+        pmw3389: Option<PMW3389T>,
+        // the sensor is to be powered up (on resume), until `reinit` is spawned
+        power_up: bool,
         led: PA9<Output<PushPull>>,
         r_click: PA1<Input<PullUp>>,
         l_click: PA0<Input<PullUp>>,
@@ -108,15 +148,16 @@ const APP: () = {
         M2_click: PA5<Input<PullUp>>,
         scl_plus: PA2<Input<PullUp>>,
         scl_minus: PA3<Input<PullUp>>,
-        Scaler: f32, //rtic::Mutex,
+        Scaler: MotionScaler, //rtic::Mutex,
         Counter: u8,
         Scale_modify: bool,
     }
     
-    #[init(schedule = [toggle_speed])]
+    #[init(schedule = [toggle_speed, sample])]
     fn init(cx: init::Context) -> init::LateResources {
         static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
         static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+        static mut SERIAL: [u8; usb_id::SERIAL_LEN] = [0; usb_id::SERIAL_LEN];
         rtt_init_print!();
         rprintln!("init");
         let mut core = cx.core;
@@ -155,15 +196,50 @@ const APP: () = {
         };
 	
         USB_BUS.replace(UsbBus::new(usb, EP_MEMORY));
-        let hid = HIDClass::new(USB_BUS.as_ref().unwrap(), PMouseReport::desc(), 1);
 
+        let (pwr, exti) = (&cx.device.PWR, &cx.device.EXTI);
+        let stop_mode = StopMode::new(core.SCB, pwr, exti);
+        let wakeup = WakeupTimer::new(cx.device.RTC, pwr, exti);
+
+        // the stored configuration, or by default side buttons as copy (M1)
+        // and paste (M2)
+        let flash = ConfigFlash::new(cx.device.FLASH);
+        let mut config = flash.read().unwrap_or_else(|| {
+            let mut config = Config::default();
+            config.buttons.set(4, Action::Key { modifiers: keyboard::MOD_LCTRL, key: 0x06 }).unwrap(); // Ctrl+C
+            config.buttons.set(3, Action::Key { modifiers: keyboard::MOD_LCTRL, key: 0x19 }).unwrap(); // Ctrl+V
+            config
+        });
+        // without a keyboard, the buttons mapped to keys act as mouse buttons
+        if !KEYBOARD && config.buttons.has_keys() {
+            rprintln!("no keyboard interface, keys mapped as mouse buttons");
+            config.buttons.clear_keys();
+        }
+
+        // polled at the configured interval
+        let hid = MouseClass::new(USB_BUS.as_ref().unwrap(), config.poll_ms);
+        // composite device, the keyboard interface next to the mouse
+        #[cfg(not(feature = "console"))]
+        let (kbd, serial) = (Some(KeyboardClass::new(USB_BUS.as_ref().unwrap())), None);
+        // or the serial console
+        #[cfg(feature = "console")]
+        let (kbd, serial) = (None, Some(SerialClass::new(USB_BUS.as_ref().unwrap())));
+        // firmware updates by dfu-util, no endpoints
+        let dfu = DfuRuntimeClass::new(USB_BUS.as_ref().unwrap());
 
-        let usb_dev = UsbDeviceBuilder::new(USB_BUS.as_ref().unwrap(), UsbVidPid(0xc410, 0x0000))
-            .manufacturer("Mouse company")
-            .product("Mouse")
-            .serial_number("Serial_Number")
+
+        let serial_number = usb_id::serial_number(&usb_id::uid(), SERIAL);
+        let usb_dev = UsbDeviceBuilder::new(USB_BUS.as_ref().unwrap(), UsbVidPid(usb_id::VID, usb_id::PID))
+            .manufacturer(usb_id::MANUFACTURER)
+            .product(usb_id::PRODUCT)
+            .serial_number(serial_number)
+            .device_release(usb_id::BCD_DEVICE)
             .device_class(0)
-            .build();
+            .supports_remote_wakeup(true);
+        // the serial port is a function of two interfaces
+        #[cfg(feature = "console")]
+        let usb_dev = usb_dev.composite_with_iads();
+        let usb_dev = usb_dev.build();
 
         let gpiob = cx.device.GPIOB.split();
         let gpioc = cx.device.GPIOC.split();
@@ -173,6 +249,16 @@ const APP: () = {
         let mosi = gpioc.pc3.into_alternate_af5();
         let cs = gpiob.pb4.into_push_pull_output().set_speed(Speed::High);
 
+        // wheel encoder, common to GND, at rest in a detent
+        let wheel_a = gpiob.pb6.into_pull_up_input();
+        let wheel_b = gpiob.pb7.into_pull_up_input();
+        let wheel = Quadrature::new(
+            wheel_a.is_high().unwrap(),
+            wheel_b.is_high().unwrap(),
+            WHEEL_TRANSITIONS,
+            WHEEL_DEBOUNCE,
+        );
+
         let spi = Spi::spi2(
             cx.device.SPI2,
             (sck, miso, mosi),
@@ -183,19 +269,37 @@ const APP: () = {
 
         let mut delay = DwtDelay::new(&mut core.DWT, clocks);
         let mut pmw3389 = pmw3389::Pmw3389::new(spi, cs, delay).unwrap();
+        pmw3389.set_cpi(config.profiles.cpi()).unwrap();
+        pmw3389.write_register(Register::LiftConfig, config.lift.register()).unwrap();
         
-        let scaler = 1.0;
+        let scaler = MotionScaler::default();
         let scale_modify = false;
 
         let now = cx.start;
             
         cx.schedule.toggle_speed(now + ((OFFSET)).cycles()).unwrap();
+        cx.schedule.sample(now + SAMPLE.cycles()).unwrap();
         //cx.schedule.toggle(now + ((OFFSET)).cycles()).unwrap();
 	
         // pass on late resources
         init::LateResources {
             //GPIOA: device.GPIOA,
             hid,
+            queue: ReportQueue::new(),
+            stats: ReportStats::new(config.poll_ms),
+            scroll: Scroll::new(SCROLL_COUNTS),
+            wheel,
+            wheel_a,
+            wheel_b,
+            suspend: Suspend::new(),
+            stop_mode,
+            wakeup,
+            kbd,
+            serial,
+            dfu,
+            console: Console::new(),
+            config,
+            flash,
             usb_dev,
             led: gpioa.pa9.into_push_pull_output(), //split the GPIOA into pins, choose pa5 and convert into push/pull output (this took a while to figure out)
             r_click: gpioa.pa1.into_pull_up_input(),
@@ -208,26 +312,49 @@ const APP: () = {
             Scaler: scaler,
             Counter: 0,
             Scale_modify: scale_modify,
-            pmw3389,
+            pmw3389: Some(pmw3389),
+            power_up: false,
             }      
     }
 
-    #[idle]
-    fn idle(_cx: idle::Context) -> ! {
+    #[idle(resources = [suspend, usb_dev, stop_mode])]
+    fn idle(cx: idle::Context) -> ! {
         rprintln!("idle");
+        let (mut suspend, mut usb_dev) = (cx.resources.suspend, cx.resources.usb_dev);
+        let stop_mode = cx.resources.stop_mode;
         loop {
-            continue;
+            // Stop mode while suspended, else sleep until the next interrupt,
+            // decided with interrupts masked so a resume can't slip in between
+            // (the bus may be resumed before `sample` sees it)
+            interrupt::free(|_| {
+                if suspend.lock(|suspend| suspend.may_stop())
+                    && usb_dev.lock(|usb_dev| usb_dev.state() == UsbDeviceState::Suspend)
+                {
+                    stop_mode.enter();
+                } else {
+                    wfi();
+                }
+            });
         }
     }
 
     //Increase or lower frequency
-    #[task(resources = [scl_minus, scl_plus, Scaler, Scale_modify], priority = 1, schedule = [toggle_speed])]
+    #[task(resources = [scl_minus, scl_plus, Scaler, Scale_modify, hid, kbd, stats], priority = 1, schedule = [toggle_speed])]
     fn toggle_speed(mut cx: toggle_speed::Context) {
+        // an idle report counts as any other
+        if cx.resources.hid.lock(|hid| hid.tick(OFFSET_MS)) {
+            cx.resources.stats.lock(|stats| stats.sent());
+        }
+        cx.resources.kbd.lock(|kbd| {
+            if let Some(kbd) = kbd {
+                kbd.tick(OFFSET_MS);
+            }
+        });
         let Scale_modify = *cx.resources.Scale_modify;
             if (cx.resources.scl_plus.is_high().unwrap() && !*cx.resources.Scale_modify){
                 *cx.resources.Scale_modify = true;
                 cx.resources.Scaler.lock(|Scaler| {
-                    *Scaler += 0.1;
+                    Scaler.set_factor(Scaler.factor() + SCALE_STEP);
                 });
             }
             else{
@@ -238,11 +365,11 @@ const APP: () = {
             if (cx.resources.scl_minus.is_high().unwrap() && !*cx.resources.Scale_modify){
                 *cx.resources.Scale_modify = true;
                 cx.resources.Scaler.lock(|Scaler| {
-                if *Scaler != 1.0 && !(*Scaler < 1.0){
-                    *Scaler -= 0.1;
+                if Scaler.factor() > scaler::ONE + SCALE_STEP {
+                    Scaler.set_factor(Scaler.factor() - SCALE_STEP);
                 }
                 else{
-                    *Scaler = 1.0;
+                    Scaler.set_factor(scaler::ONE);
                 }
                 });
             }
@@ -258,57 +385,313 @@ const APP: () = {
         fn EXTI0();
     }
     
-    #[task(binds=OTG_FS, resources = [led, r_click, l_click, w_click, M1_click, M2_click, Scaler, hid, pmw3389, usb_dev], priority = 2)]
-    fn toggle(cx: toggle::Context) {
+    // Accumulates the sensor motion and buttons, sent as the endpoint is free
+    #[task(resources = [r_click, l_click, w_click, M1_click, M2_click, Scaler, hid, kbd, config, pmw3389, queue, stats, scroll, wheel, wheel_a, wheel_b, usb_dev, suspend, power_up, wakeup], priority = 2, schedule = [sample], spawn = [reinit])]
+    fn sample(cx: sample::Context) {
         let myScaler = cx.resources.Scaler;
-        let hid = cx.resources.hid;
+        let queue = cx.resources.queue;
+        let scroll = cx.resources.scroll;
         let r_click = cx.resources.r_click;
         let l_click = cx.resources.l_click;
         let w_click = cx.resources.w_click;
         let M1_click = cx.resources.M1_click;
         let M2_click = cx.resources.M2_click;
+
+        let suspend = cx.resources.suspend;
+        let pmw3389 = cx.resources.pmw3389;
+        let power_up = cx.resources.power_up;
+
+        // no motion from a sensor shut down, or being re-initialized
+        let (x, y) = match pmw3389.as_mut() {
+            Some(pmw3389) if !suspend.is_shutdown() => pmw3389.read_status().unwrap(),
+            _ => (0, 0),
+        };
+        let (x, y) = myScaler.scale(x, y);
+        let pressed = (M1_click.is_high().unwrap() as u8) << 4
+            | (M2_click.is_high().unwrap() as u8) << 3
+            | (w_click.is_high().unwrap() as u8) << 2
+            | (r_click.is_high().unwrap() as u8) << 1
+            | (l_click.is_high().unwrap() as u8);
+        let wheel = cx.resources.wheel;
+        wheel.update(
+            cx.resources.wheel_a.is_high().unwrap(),
+            cx.resources.wheel_b.is_high().unwrap(),
+        );
+        let detents = wheel.take_detents();
+
+        // the time since the previous sample
+        let ms = if suspend.is_suspended() { SUSPEND_SAMPLE_MS } else { 1 };
         let usb_dev = cx.resources.usb_dev;
-        let mut POS_X: i64 = 0;
-        let mut POS_Y: i64 = 0;
-        if M1_click.is_high().unwrap() {
-            //if cx.resources.led.is_low().unwrap(){
-               _toggleable_generic(cx.resources.led); //Utilize the generic toggle function, toggle variable no longer needed
-               //}
-        }
-        if M2_click.is_high().unwrap() {
-            //if cx.resources.led.is_low().unwrap(){
-               _toggleable_generic(cx.resources.led); //Utilize the generic toggle function, toggle variable no longer needed
-               //}
+        let event = suspend.update(
+            usb_dev.state() == UsbDeviceState::Suspend,
+            usb_dev.remote_wakeup_enabled(),
+            pressed != 0 || x != 0 || y != 0 || detents != 0,
+            ms,
+        );
+        match event {
+            Some(Event::Suspend { shutdown }) => {
+                rprintln!("suspend");
+                cx.resources.wakeup.start(SUSPEND_SAMPLE_MS);
+                if shutdown {
+                    if let Some(pmw3389) = pmw3389.as_mut() {
+                        pmw3389.shutdown().ok();
+                    }
+                }
+            }
+            Some(Event::SignalResume) => {
+                rprintln!("remote wakeup");
+                signal_resume(true);
+            }
+            Some(Event::EndResume) => signal_resume(false),
+            Some(Event::Resume { power_up: shut_down }) => {
+                rprintln!("resume");
+                cx.resources.wakeup.disable();
+                *power_up |= shut_down;
+            }
+            None => {}
         }
-        else{
-            if cx.resources.led.is_low().unwrap() && r_click.is_low().unwrap() && l_click.is_low().unwrap() {
-                _toggleable_generic(cx.resources.led);
+        // a disconnected (or shut down) sensor is re-initialized at a lower
+        // priority, kept and retried on the next sample if not spawned
+        let disconnected = pmw3389.as_ref().map(|p| p.status()) == Some(pmw3389::Status::Disconnected);
+        if *power_up || disconnected {
+            if let Some(sensor) = pmw3389.take() {
+                match cx.spawn.reinit(sensor, *power_up) {
+                    Ok(()) => *power_up = false,
+                    Err((sensor, _)) => {
+                        rprintln!("pmw3389 - reinit not spawned");
+                        *pmw3389 = Some(sensor);
+                    }
+                }
             }
         }
-        
-        let (x, y) = cx.resources.pmw3389.read_status().unwrap();
-        POS_X += x as i64;
-        POS_Y += y as i64;
-        let report = PMouseReport {
-            buttons: ((M1_click.is_high().unwrap() as u8) << 4
-                | (M2_click.is_high().unwrap() as u8) << 3
-                | (w_click.is_high().unwrap() as u8) << 2
-                | (r_click.is_high().unwrap() as u8) << 1
-                | (l_click.is_high().unwrap() as u8)),
-            x: x as i8,
-            y: y as i8,
-            wheel: 0,
-        };
-        hid.push_input(&report).ok();
-        
-        if usb_dev.poll(&mut [hid]) {
+        // nothing is reported while suspended, sampled at a lower rate by
+        // `rtc_wakeup` (the cycle counter stops in Stop mode)
+        if suspend.is_suspended() {
             return;
         }
-   
+
+        let (buttons, keys) = cx.resources.config.buttons.map(pressed);
+        // while a scroll button is held the motion scrolls
+        if cx.resources.config.buttons.scrolling(pressed) {
+            scroll.add_motion(x, y);
+        } else {
+            scroll.reset_motion();
+            queue.add_motion(x, y);
+        }
+        scroll.add_detents(detents, 0);
+        let (wheel, pan) = scroll.take(cx.resources.hid.resolution_multiplier());
+        queue.add_scroll(wheel, pan);
+        queue.set_buttons(buttons);
+        let stats = cx.resources.stats;
+        if let Ok(true) = queue.flush(cx.resources.hid) {
+            stats.sent();
+        }
+        stats.tick(1, queue.is_pending());
+        if let Some(kbd) = cx.resources.kbd {
+            kbd.set_report(&keys);
+            kbd.flush().ok();
+        }
+
+        cx.schedule.sample(cx.scheduled + SAMPLE.cycles()).unwrap();
+    }
+
+    #[task(binds=OTG_FS, resources = [led, r_click, l_click, M1_click, M2_click, hid, kbd, serial, dfu, console, queue, stats, usb_dev, config, pmw3389], priority = 2, spawn = [save], schedule = [detach])]
+    fn toggle(cx: toggle::Context) {
+        let hid = cx.resources.hid;
+        let r_click = cx.resources.r_click;
+        let l_click = cx.resources.l_click;
+        let M1_click = cx.resources.M1_click;
+        let M2_click = cx.resources.M2_click;
+        let usb_dev = cx.resources.usb_dev;
+        let config = cx.resources.config;
+        match config.led {
+            LedMode::Off => {
+                cx.resources.led.set_low().ok();
+            }
+            LedMode::On => {
+                cx.resources.led.set_high().ok();
+            }
+            LedMode::Buttons => {
+                if M1_click.is_high().unwrap() {
+                    //if cx.resources.led.is_low().unwrap(){
+                       _toggleable_generic(cx.resources.led); //Utilize the generic toggle function, toggle variable no longer needed
+                       //}
+                }
+                if M2_click.is_high().unwrap() {
+                    //if cx.resources.led.is_low().unwrap(){
+                       _toggleable_generic(cx.resources.led); //Utilize the generic toggle function, toggle variable no longer needed
+                       //}
+                }
+                else{
+                    if cx.resources.led.is_low().unwrap() && r_click.is_low().unwrap() && l_click.is_low().unwrap() {
+                        _toggleable_generic(cx.resources.led);
+                    }
+                }
+            }
+        }
+
+        let dfu = cx.resources.dfu;
+        let spawn = cx.spawn;
+        match (cx.resources.kbd, cx.resources.serial) {
+            (Some(kbd), _) => {
+                usb_dev.poll(&mut [&mut *hid, kbd, &mut *dfu]);
+                kbd.flush().ok();
+            }
+            (None, Some(serial)) => {
+                usb_dev.poll(&mut [&mut *hid, serial, &mut *dfu]);
+                let (pmw3389, stats) = (&mut *cx.resources.pmw3389, &*cx.resources.stats);
+                let mut buf = [0; cdc_acm::MAX_PACKET];
+                if let Ok(n) = serial.read(&mut buf) {
+                    cx.resources.console.receive(&buf[..n], |input, out| match input {
+                        // answered by `save`, unless still saving
+                        Input::Command(Command::Save) => {
+                            if spawn.save(Origin::Console).is_err() {
+                                out.response(&Err(Status::Failed));
+                            }
+                        }
+                        Input::Command(command) => out.response(&execute(
+                            Ok(command),
+                            config,
+                            pmw3389.as_mut(),
+                            stats,
+                        )),
+                        Input::ReadRegister(addr) => match read_register(pmw3389, addr) {
+                            Some(value) => out.register(addr, value),
+                            None => out.response(&Err(Status::Failed)),
+                        },
+                        Input::WriteRegister(addr, value) => {
+                            match write_register(pmw3389, addr, value) {
+                                Some(()) => out.register(addr, value),
+                                None => out.response(&Err(Status::Failed)),
+                            }
+                        }
+                        Input::SelfTest => match pmw3389.as_mut().map(|p| p.srom_crc()) {
+                            Some(Ok(crc)) => out.self_test(crc),
+                            _ => out.response(&Err(Status::Failed)),
+                        },
+                    });
+                }
+                send_console(serial, cx.resources.console);
+            }
+            (None, None) => {
+                usb_dev.poll(&mut [&mut *hid, &mut *dfu]);
+            }
+        }
+        if dfu.take_detach() {
+            rprintln!("dfu detach");
+            // reset once the status stage is through, without busy waiting
+            // in the interrupt
+            cx.schedule.detach(Instant::now() + DETACH_DELAY.cycles()).ok();
+        }
+        // the endpoint may be free again, send what is left
+        if let Ok(true) = cx.resources.queue.flush(hid) {
+            cx.resources.stats.sent();
+        }
+
+        if let Some(request) = hid.take_config_request() {
+            let command = Command::decode(&request);
+            // answered by `save` (pending meanwhile), unless still saving
+            if command != Ok(Command::Save) || spawn.save(Origin::Feature).is_err() {
+                let result = match command {
+                    Ok(Command::Save) => Err(Status::Failed),
+                    command => execute(command, config, cx.resources.pmw3389.as_mut(), cx.resources.stats),
+                };
+                hid.set_config_response(&config_proto::encode_response(request[1], &result));
+            }
+        }
         
         //cx.schedule.toggle(cx.scheduled + ((*myScaler as u32 * OFFSET)).cycles()).unwrap();
     }
 
+    // Samples while suspended, woken from Stop mode by the RTC
+    #[task(binds = RTC_WKUP, resources = [wakeup], priority = 2, spawn = [sample])]
+    fn rtc_wakeup(cx: rtc_wakeup::Context) {
+        cx.resources.wakeup.clear();
+        // unless a sample is still queued
+        cx.spawn.sample().ok();
+    }
+
+    // The host resumes (or resets) the bus, handled by the OTG_FS interrupt
+    // that follows on the restored clocks
+    #[task(binds = OTG_FS_WKUP, priority = 2)]
+    fn usb_wakeup(_cx: usb_wakeup::Context) {
+        low_power::clear_usb_wakeup();
+    }
+
+    // Re-initializes the sensor, after a disconnect or (`power_up`) a
+    // shutdown, retried with backoff, then restores its settings
+    //
+    // The sensor is owned meanwhile, so `sample` and the USB stack aren't
+    // held up by the reset and the firmware upload.
+    #[task(resources = [config, pmw3389], priority = 1, schedule = [reinit])]
+    fn reinit(cx: reinit::Context, mut pmw3389: PMW3389T, power_up: bool) {
+        let status = if power_up {
+            pmw3389.power_up()
+        } else {
+            pmw3389.reconnect()
+        };
+        let (mut config, mut slot) = (cx.resources.config, cx.resources.pmw3389);
+        if let Ok(pmw3389::Status::Connected) = status {
+            config.lock(|config| {
+                let result = pmw3389
+                    .set_cpi(config.profiles.cpi())
+                    .and_then(|_| pmw3389.write_register(Register::LiftConfig, config.lift.register()));
+                if result.is_err() {
+                    rprintln!("pmw3389 - restoring the settings failed");
+                }
+                slot.lock(|slot| *slot = Some(pmw3389));
+            });
+        } else {
+            let backoff = pmw3389.backoff() * SAMPLE;
+            // if not scheduled, back to `sample`, which finds it disconnected
+            if let Err((pmw3389, _)) = cx.schedule.reinit(Instant::now() + backoff.cycles(), pmw3389, false) {
+                rprintln!("pmw3389 - reinit not scheduled");
+                slot.lock(|slot| *slot = Some(pmw3389));
+            }
+        }
+    }
+
+    // Reboots into the ROM bootloader, after the DFU_DETACH status stage
+    #[task(priority = 1)]
+    fn detach(_cx: detach::Context) {
+        dfu::enter_bootloader();
+    }
+
+    // Stores the configuration in flash, then answers the request
+    //
+    // The sector erase takes 1-2 s, so it is done at the lowest priority,
+    // not in the USB interrupt.
+    #[task(resources = [config, flash, hid, console, serial], priority = 1)]
+    fn save(cx: save::Context, origin: Origin) {
+        let mut config = cx.resources.config;
+        let config = config.lock(|config| *config);
+        let result = match cx.resources.flash.write(&config) {
+            Ok(()) => Ok(Response::Done),
+            Err(e) => {
+                rprintln!("flash error {:?}", e);
+                Err(Status::Failed)
+            }
+        };
+        match origin {
+            Origin::Feature => {
+                let response = config_proto::encode_response(Command::Save.id(), &result);
+                let mut hid = cx.resources.hid;
+                hid.lock(|hid| hid.set_config_response(&response));
+            }
+            Origin::Console => {
+                let (mut console, mut serial) = (cx.resources.console, cx.resources.serial);
+                console.lock(|console| {
+                    console.output().response(&result);
+                    serial.lock(|serial| {
+                        if let Some(serial) = serial {
+                            send_console(serial, console);
+                        }
+                    });
+                });
+            }
+        }
+    }
+
     extern "C" {
         fn EXTI1();
     }
@@ -318,6 +701,137 @@ const APP: () = {
     }
 };
 
+// Starts or ends the resume signalling (OTG_FS_DCTL RWUSIG), the USB driver
+// has no support for remote wakeup
+//
+// RWUSIG is to be held 1 to 15 ms. It is set on `SignalResume` and cleared
+// on the `EndResume` of the next `sample`, woken by the RTC wakeup timer
+// `SUSPEND_SAMPLE_MS` (5 ms, `suspend::RESUME_MS`, 3.4 to 9.4 ms over the
+// LSI tolerance) later, the MCU is not stopped meanwhile. Only the USB
+// interrupt, at the same priority, can delay the clear.
+fn signal_resume(on: bool) {
+    let device = unsafe { &*stm32::OTG_FS_DEVICE::ptr() };
+    device.dctl.modify(|_, w| w.rwusig().bit(on));
+}
+
+// Sends as much of the console output as the endpoint takes
+fn send_console(serial: &mut SerialClass<'static, UsbBusType>, console: &mut Console) {
+    let out = console.output();
+    if let Ok(n) = serial.write(out.pending()) {
+        out.consume(n);
+    }
+}
+
+// Carries out a command, of the feature report or the console, `pmw3389`
+// is `None` while the sensor is re-initialized (the settings are then
+// restored from `config`)
+//
+// `Save` is carried out by the `save` task.
+fn execute(
+    command: Result<Command, Status>,
+    config: &mut Config,
+    pmw3389: Option<&mut PMW3389T>,
+    stats: &ReportStats,
+) -> Result<Response, Status> {
+    match command {
+        Ok(Command::GetVersion) => Ok(Response::Version(config_proto::VERSION)),
+        Ok(Command::GetCpi) => Ok(Response::Cpi(config.profiles.cpi())),
+        Ok(Command::SetCpi(cpi)) => match apply(pmw3389, |p| p.set_cpi(cpi)) {
+            Ok(()) => {
+                config.profiles.set_cpi(cpi);
+                Ok(Response::Done)
+            }
+            Err(_) => Err(Status::Failed),
+        },
+        Ok(Command::GetProfiles) => Ok(Response::Profiles(config.profiles)),
+        Ok(Command::SetProfiles(profiles)) => match apply(pmw3389, |p| p.set_cpi(profiles.cpi())) {
+            Ok(()) => {
+                config.profiles = profiles;
+                Ok(Response::Done)
+            }
+            Err(_) => Err(Status::Failed),
+        },
+        Ok(Command::GetButtonMap) => Ok(Response::ButtonMap(config.buttons)),
+        // keys are not reported without the keyboard interface
+        Ok(Command::SetButtonMap(map)) if !KEYBOARD && map.has_keys() => Err(Status::BadArgument),
+        Ok(Command::SetButtonMap(map)) => {
+            config.buttons = map;
+            Ok(Response::Done)
+        }
+        Ok(Command::GetPollInterval) => Ok(Response::PollInterval(config.poll_ms)),
+        Ok(Command::SetPollInterval(ms)) => {
+            // takes effect on the next start
+            config.poll_ms = ms;
+            Ok(Response::Done)
+        }
+        Ok(Command::GetLift) => Ok(Response::Lift(config.lift)),
+        Ok(Command::SetLift(lift)) => match apply(pmw3389, |p| p.write_register(Register::LiftConfig, lift.register())) {
+            Ok(()) => {
+                config.lift = lift;
+                Ok(Response::Done)
+            }
+            Err(_) => Err(Status::Failed),
+        },
+        Ok(Command::GetLed) => Ok(Response::Led(config.led)),
+        Ok(Command::SetLed(led)) => {
+            config.led = led;
+            Ok(Response::Done)
+        }
+        Ok(Command::Save) => Err(Status::Pending),
+        Ok(Command::GetDiagnostics) => {
+            let stats = Diagnostics {
+                report_rate: stats.rate(),
+                missed: stats.missed(),
+                empty: stats.empty(),
+                ..Default::default()
+            };
+            let pmw3389 = match pmw3389 {
+                Some(pmw3389) => pmw3389,
+                // being re-initialized, not connected
+                None => return Ok(Response::Diagnostics(stats)),
+            };
+            let ids = pmw3389
+                .product_id()
+                .and_then(|id| Ok((id, pmw3389.read_register(Register::SROMId)?)));
+            match ids {
+                Ok((product_id, srom_id)) => Ok(Response::Diagnostics(Diagnostics {
+                    connected: pmw3389.status() == pmw3389::Status::Connected,
+                    product_id,
+                    srom_id,
+                    squal: pmw3389.squal(),
+                    shutter: pmw3389.shutter(),
+                    ..stats
+                })),
+                Err(_) => Err(Status::Failed),
+            }
+        }
+        Err(status) => Err(status),
+    }
+}
+
+// Applies a setting to the sensor, if not being re-initialized
+fn apply<F, E>(pmw3389: Option<&mut PMW3389T>, f: F) -> Result<(), E>
+where
+    F: FnOnce(&mut PMW3389T) -> Result<(), E>,
+{
+    match pmw3389 {
+        Some(pmw3389) => f(pmw3389),
+        None => Ok(()),
+    }
+}
+
+// Reads a sensor register, `None` if not in the register map, on error or
+// while re-initialized
+fn read_register(pmw3389: &mut Option<PMW3389T>, addr: u8) -> Option<u8> {
+    pmw3389.as_mut()?.read_register(Register::from_addr(addr)?).ok()
+}
+
+// Writes a sensor register, `None` if not in the register map, on error or
+// while re-initialized
+fn write_register(pmw3389: &mut Option<PMW3389T>, addr: u8, value: u8) -> Option<()> {
+    pmw3389.as_mut()?.write_register(Register::from_addr(addr)?, value).ok()
+}
+
 fn _toggle_generic<E>(led: &mut dyn OutputPin<Error = E>, toggle: &mut bool) {
     if *toggle {
         led.set_high().ok();
@@ -0,0 +1,18 @@
+//! Host stand-in for `rtt-target`
+//!
+//! The drivers shared with the firmware trace through `rprintln!`, on
+//! the host the output goes to stdout.
+
+#[macro_export]
+macro_rules! rprintln {
+    ($($arg:tt)*) => {
+        println!($($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! rprint {
+    ($($arg:tt)*) => {
+        print!($($arg)*)
+    };
+}
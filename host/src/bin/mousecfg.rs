@@ -0,0 +1,213 @@
+//! Configures the mouse over its vendor HID feature report
+//!
+//! > cargo run --bin mousecfg -- [--dev /dev/hidrawN] COMMAND [ARGS]
+//!
+//! Commands:
+//!   version                  protocol version of the firmware
+//!   cpi [CPI]                resolution of the active profile
+//!   profiles [N CPI CPI CPI CPI]
+//!                            DPI profiles, N the active one
+//!   buttons [B=ACTION ..]    button map, B 0..4 (left, right, wheel, M2, M1),
+//!                            ACTION none, mouse:BIT, key:MODIFIERS:USAGE or
+//!                            scroll (motion scrolls while held)
+//!   poll [1|2|4|8]           polling interval (bInterval) in ms, on the next
+//!                            start
+//!   lift [2|3]               lift-off distance in mm
+//!   led [off|on|buttons]     LED mode
+//!   save                     stores the configuration in flash
+//!   diag                     sensor diagnostics and report rate
+//!
+//! Without `--dev` the first hidraw node of the mouse answering the
+//! protocol is used. Uses the firmware's `config_proto`, the firmware and
+//! the tool must be of the same protocol version.
+
+use std::process;
+
+use host::buttons::{Action, ButtonMap, BUTTONS};
+use host::config_proto::{
+    Command, LedMode, Lift, Profiles, Response, PROFILES,
+};
+use host::hidraw::{Error, Hidraw};
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: mousecfg [--dev PATH] version | cpi [CPI] | profiles [N CPI CPI CPI CPI] | \
+         buttons [B=ACTION ..] | poll [1|2|4|8] | lift [2|3] | led [off|on|buttons] | save | \
+         diag"
+    );
+    process::exit(2);
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> T {
+    s.parse().unwrap_or_else(|_| usage())
+}
+
+// none, mouse:BIT, key:MODIFIERS:USAGE (numbers in decimal or 0x hex) or
+// scroll
+fn parse_action(s: &str) -> Option<Action> {
+    let num = |s: &str| match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    let fields: Vec<&str> = s.split(':').collect();
+    match fields.as_slice() {
+        ["none"] => Some(Action::None),
+        ["scroll"] => Some(Action::Scroll),
+        ["mouse", bit] => num(bit).map(Action::Mouse),
+        ["key", modifiers, key] => Some(Action::Key {
+            modifiers: num(modifiers)?,
+            key: num(key)?,
+        }),
+        _ => None,
+    }
+}
+
+fn format_action(action: Action) -> String {
+    match action {
+        Action::None => "none".into(),
+        Action::Mouse(bit) => format!("mouse:{}", bit),
+        Action::Key { modifiers, key } => format!("key:0x{:02x}:0x{:02x}", modifiers, key),
+        Action::Scroll => "scroll".into(),
+    }
+}
+
+fn print(response: &Response) {
+    match response {
+        Response::Version(v) => println!("protocol version {}", v),
+        Response::Cpi(cpi) => println!("{} cpi", cpi),
+        Response::Profiles(p) => {
+            for (n, cpi) in p.cpi.iter().enumerate() {
+                let active = if n == p.active as usize { "*" } else { " " };
+                println!("{} {}: {} cpi", active, n, cpi);
+            }
+        }
+        Response::ButtonMap(map) => {
+            for n in 0..BUTTONS {
+                println!("{}={}", n, format_action(map.action(n)));
+            }
+        }
+        Response::PollInterval(ms) => println!("{} ms", ms),
+        Response::Lift(Lift::Mm2) => println!("2 mm"),
+        Response::Lift(Lift::Mm3) => println!("3 mm"),
+        Response::Led(led) => println!("{:?}", led),
+        Response::Diagnostics(d) => {
+            println!("connected   {}", d.connected);
+            println!("product id  0x{:02x}", d.product_id);
+            println!("srom id     0x{:02x}", d.srom_id);
+            println!("squal       {}", d.squal);
+            println!("shutter     {}", d.shutter);
+            println!("report rate {}/s", d.report_rate);
+            println!("missed      {}", d.missed);
+            println!("empty       {}", d.empty);
+        }
+        Response::Done => {}
+    }
+}
+
+// The command of the arguments
+fn command(dev: &Hidraw, args: &[String]) -> Result<Command, Error> {
+    let (name, args) = args.split_first().unwrap_or_else(|| usage());
+    Ok(match (name.as_str(), args.len()) {
+        ("version", 0) => Command::GetVersion,
+        ("cpi", 0) => Command::GetCpi,
+        ("cpi", 1) => Command::SetCpi(parse(&args[0])),
+        ("profiles", 0) => Command::GetProfiles,
+        ("profiles", n) if n == PROFILES + 1 => {
+            let mut profiles = Profiles {
+                active: parse(&args[0]),
+                ..Profiles::default()
+            };
+            for (cpi, arg) in profiles.cpi.iter_mut().zip(&args[1..]) {
+                *cpi = parse(arg);
+            }
+            Command::SetProfiles(profiles)
+        }
+        ("buttons", 0) => Command::GetButtonMap,
+        ("buttons", _) => {
+            // changes on top of the current map
+            let mut map = match dev.command(&Command::GetButtonMap)? {
+                Response::ButtonMap(map) => map,
+                _ => ButtonMap::default(),
+            };
+            for arg in args {
+                let (button, action) = arg.split_once('=').unwrap_or_else(|| usage());
+                let button: usize = parse(button);
+                if button >= BUTTONS {
+                    usage();
+                }
+                let action = parse_action(action).unwrap_or_else(|| usage());
+                map.set(button, action).unwrap_or_else(|_| usage());
+            }
+            Command::SetButtonMap(map)
+        }
+        ("poll", 0) => Command::GetPollInterval,
+        ("poll", 1) => Command::SetPollInterval(parse(&args[0])),
+        ("lift", 0) => Command::GetLift,
+        ("lift", 1) => Command::SetLift(match args[0].as_str() {
+            "2" => Lift::Mm2,
+            "3" => Lift::Mm3,
+            _ => usage(),
+        }),
+        ("led", 0) => Command::GetLed,
+        ("led", 1) => Command::SetLed(match args[0].as_str() {
+            "off" => LedMode::Off,
+            "on" => LedMode::On,
+            "buttons" => LedMode::Buttons,
+            _ => usage(),
+        }),
+        ("save", 0) => Command::Save,
+        ("diag", 0) => Command::GetDiagnostics,
+        _ => usage(),
+    })
+}
+
+// The first node answering GetVersion
+fn open() -> Result<Hidraw, String> {
+    let nodes = Hidraw::find().map_err(|e| format!("/sys/class/hidraw: {}", e))?;
+    let mut last = "mouse not found".to_string();
+    for node in nodes {
+        let result = Hidraw::open(&node)
+            .map_err(Error::Io)
+            .and_then(|dev| dev.command(&Command::GetVersion).map(|_| dev));
+        match result {
+            Ok(dev) => return Ok(dev),
+            Err(e) => last = format!("{}: {}", node.display(), e),
+        }
+    }
+    Err(last)
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut path = None;
+    if args.first().map(|a| a.as_str()) == Some("--dev") {
+        if args.len() < 2 {
+            usage();
+        }
+        path = Some(args.remove(1));
+        args.remove(0);
+    }
+
+    match args.first().map(|a| a.as_str()) {
+        None | Some("-h") | Some("--help") => usage(),
+        _ => {}
+    }
+
+    let dev = match path {
+        Some(path) => Hidraw::open(&path).map_err(|e| format!("{}: {}", path, e)),
+        None => open(),
+    };
+    let dev = dev.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let result = command(&dev, &args).and_then(|command| dev.command(&command));
+    match result {
+        Ok(response) => print(&response),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
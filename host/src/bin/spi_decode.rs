@@ -0,0 +1,118 @@
+//! Decodes a logic analyzer capture of the PMW3389 SPI bus
+//!
+//! > cargo run --bin spi_decode -- [options] capture.csv
+//!
+//! Options:
+//!   --sck NAME, --mosi NAME, --miso NAME, --ncs NAME
+//!                      column names of the signals (default SCK, MOSI, MISO, NCS)
+//!   --samplerate HZ    samplerate, used if the capture has no time column
+//!   --mode N           SPI mode (default 3)
+//!
+//! Use `-` as file name to read from stdin.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+
+use host::capture::{self, Channels};
+use host::decode::{self, Access};
+use host::register::Register;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: spi_decode [--sck NAME] [--mosi NAME] [--miso NAME] [--ncs NAME] \
+         [--samplerate HZ] [--mode N] FILE"
+    );
+    process::exit(2);
+}
+
+fn reg_name(addr: u8) -> String {
+    match Register::from_addr(addr) {
+        Some(reg) => format!("{:?}", reg),
+        None => "Unknown".into(),
+    }
+}
+
+fn main() {
+    let mut channels = Channels::default();
+    let mut samplerate = None;
+    let mut mode = 3;
+    let mut file = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--sck" => channels.sck = value(),
+            "--mosi" => channels.mosi = value(),
+            "--miso" => channels.miso = value(),
+            "--ncs" => channels.ncs = value(),
+            "--samplerate" => samplerate = Some(value().parse().unwrap_or_else(|_| usage())),
+            "--mode" => mode = value().parse().unwrap_or_else(|_| usage()),
+            "-h" | "--help" => usage(),
+            _ if file.is_none() => file = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let file = file.unwrap_or_else(|| usage());
+    let samples = if file == "-" {
+        capture::parse(io::stdin().lock(), &channels, samplerate)
+    } else {
+        match File::open(&file) {
+            Ok(f) => capture::parse(BufReader::new(f), &channels, samplerate),
+            Err(e) => Err(e.into()),
+        }
+    };
+
+    let samples = samples.unwrap_or_else(|e| {
+        eprintln!("{}: {}", file, e);
+        process::exit(1);
+    });
+
+    let transactions = decode::transactions(&samples, mode);
+    let violations = decode::check(&transactions);
+
+    for (index, tr) in transactions.iter().enumerate() {
+        let t = tr.select * 1e3;
+        match tr.access() {
+            Access::Reset => println!("{:12.6} ms  NCS pulse (serial port reset)", t),
+            Access::Write { addr, data } => println!(
+                "{:12.6} ms  W {} (0x{:02x}) <- {:02x?}",
+                t,
+                reg_name(addr),
+                addr,
+                data
+            ),
+            Access::Read { addr, data } => println!(
+                "{:12.6} ms  R {} (0x{:02x}) -> {:02x?}",
+                t,
+                reg_name(addr),
+                addr,
+                data
+            ),
+        }
+
+        if tr.partial_bits != 0 {
+            println!("    ! {} trailing bits (incomplete byte)", tr.partial_bits);
+        }
+        if tr.deselect.is_none() {
+            println!("    ! capture ended with NCS asserted");
+        }
+
+        for v in violations.iter().filter(|v| v.index == index) {
+            println!(
+                "    ! {} {:.3} us < {:.3} us",
+                v.what,
+                v.actual * 1e6,
+                v.limit * 1e6
+            );
+        }
+    }
+
+    println!(
+        "{} transactions, {} timing violations",
+        transactions.len(),
+        violations.len()
+    );
+}
@@ -0,0 +1,218 @@
+//! Reading sigrok/PulseView CSV exports
+//!
+//! The expected format is the one produced by `sigrok-cli -O csv` (or
+//! PulseView "Export Comma-Separated Values"), e.g.:
+//!
+//! ```text
+//! ; CSV generated by libsigrok 0.5.2
+//! ; Channels (4/8): D0, D1, D2, D3
+//! ; Samplerate: 24 MHz
+//! Time [us],SCK,MOSI,MISO,NCS
+//! 0.000000,1,0,0,1
+//! ```
+//!
+//! The time column is optional, if missing each row is taken as one sample
+//! at the given (or reported) samplerate.
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// One sample of the four SPI signals, `time` in seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub time: f64,
+    pub sck: bool,
+    pub mosi: bool,
+    pub miso: bool,
+    pub ncs: bool,
+}
+
+/// Column names of the SPI signals in the capture
+#[derive(Clone, Debug)]
+pub struct Channels {
+    pub sck: String,
+    pub mosi: String,
+    pub miso: String,
+    pub ncs: String,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels {
+            sck: "SCK".into(),
+            mosi: "MOSI".into(),
+            miso: "MISO".into(),
+            ncs: "NCS".into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    MissingHeader,
+    MissingChannel(String),
+    NoTimebase,
+    Parse { line: usize, msg: String },
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+            Error::MissingHeader => write!(f, "no header line found"),
+            Error::MissingChannel(name) => write!(f, "channel `{}` not found in header", name),
+            Error::NoTimebase => write!(
+                f,
+                "no time column and no samplerate given (use --samplerate)"
+            ),
+            Error::Parse { line, msg } => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+/// Parses a value like "24 MHz" (as found in the "; Samplerate:" comment)
+fn parse_samplerate(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let value: f64 = s[..split].trim().parse().ok()?;
+    let scale = match s[split..].trim().to_ascii_lowercase().as_str() {
+        "" | "hz" => 1.0,
+        "khz" => 1e3,
+        "mhz" => 1e6,
+        "ghz" => 1e9,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+/// Scale to seconds from the unit of a time column header, e.g. "Time [us]"
+fn time_scale(header: &str) -> f64 {
+    let unit = header
+        .find('[')
+        .and_then(|start| {
+            header[start + 1..]
+                .find(']')
+                .map(|end| &header[start + 1..start + 1 + end])
+        })
+        .unwrap_or("s");
+    match unit.trim() {
+        "ms" => 1e-3,
+        "us" | "µs" => 1e-6,
+        "ns" => 1e-9,
+        _ => 1.0,
+    }
+}
+
+fn column(header: &[&str], name: &str) -> Result<usize, Error> {
+    header
+        .iter()
+        .position(|h| h.eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::MissingChannel(name.into()))
+}
+
+fn level(field: Option<&&str>, line: usize) -> Result<bool, Error> {
+    match field.map(|f| f.trim()) {
+        Some("0") => Ok(false),
+        Some("1") => Ok(true),
+        other => Err(Error::Parse {
+            line,
+            msg: format!("expected logic level 0/1, found {:?}", other),
+        }),
+    }
+}
+
+/// Reads all samples of a capture
+///
+/// `samplerate` (in Hz) overrides the samplerate reported in the file,
+/// it is only used if the capture lacks a time column.
+pub fn parse<R: BufRead>(
+    reader: R,
+    channels: &Channels,
+    samplerate: Option<f64>,
+) -> Result<Vec<Sample>, Error> {
+    let mut reported_samplerate = None;
+    let mut layout = None;
+    let mut samples = Vec::new();
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_nr = n + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with(';') || line.starts_with('#') {
+            let comment = line[1..].trim();
+            if comment.to_ascii_lowercase().starts_with("samplerate:") {
+                reported_samplerate = parse_samplerate(&comment["samplerate:".len()..]);
+            }
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+
+        let (time, sck, mosi, miso, ncs, period) = match layout {
+            Some(layout) => layout,
+            None => {
+                // first non comment line is the header
+                let time = fields
+                    .iter()
+                    .position(|h| h.to_ascii_lowercase().starts_with("time"))
+                    .map(|i| (i, time_scale(fields[i])));
+                let period = match (time, samplerate.or(reported_samplerate)) {
+                    (Some(_), _) => 0.0,
+                    (None, Some(rate)) => 1.0 / rate,
+                    (None, None) => return Err(Error::NoTimebase),
+                };
+                layout = Some((
+                    time,
+                    column(&fields, &channels.sck)?,
+                    column(&fields, &channels.mosi)?,
+                    column(&fields, &channels.miso)?,
+                    column(&fields, &channels.ncs)?,
+                    period,
+                ));
+                continue;
+            }
+        };
+
+        let time = match time {
+            Some((i, scale)) => {
+                let value: f64 = fields
+                    .get(i)
+                    .and_then(|f| f.parse().ok())
+                    .ok_or_else(|| Error::Parse {
+                        line: line_nr,
+                        msg: "bad time value".into(),
+                    })?;
+                value * scale
+            }
+            None => samples.len() as f64 * period,
+        };
+
+        samples.push(Sample {
+            time,
+            sck: level(fields.get(sck), line_nr)?,
+            mosi: level(fields.get(mosi), line_nr)?,
+            miso: level(fields.get(miso), line_nr)?,
+            ncs: level(fields.get(ncs), line_nr)?,
+        });
+    }
+
+    if layout.is_none() {
+        return Err(Error::MissingHeader);
+    }
+
+    Ok(samples)
+}
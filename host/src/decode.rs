@@ -0,0 +1,249 @@
+//! SPI transaction reconstruction and PMW3389 protocol checking
+//!
+//! Timing limits are taken from the PMW3389DM-T3QU datasheet
+//! (section "Serial Peripheral Interface").
+
+use crate::capture::Sample;
+use crate::register::Register;
+
+// SCLK period, 2 MHz max (here the half period)
+pub const T_SCLK_HALF: f64 = 250e-9;
+// NCS falling edge to first SCLK edge
+pub const T_NCS_SCLK: f64 = 120e-9;
+// last SCLK edge to NCS rising edge, read operation
+pub const T_SCLK_NCS_READ: f64 = 120e-9;
+// last SCLK edge to NCS rising edge, write operation
+pub const T_SCLK_NCS_WRITE: f64 = 35e-6;
+// address to data delay, read operation
+pub const T_SRAD: f64 = 160e-6;
+// address to data delay, motion burst read
+pub const T_SRAD_MOTBR: f64 = 35e-6;
+// write to subsequent write/read (tSWW/tSWR)
+pub const T_SWW: f64 = 180e-6;
+// read to subsequent write/read (tSRW/tSRR)
+pub const T_SRW: f64 = 20e-6;
+// NCS high to exit motion burst mode
+pub const T_BEXIT: f64 = 500e-9;
+// delay between bytes of the SROM download
+pub const T_LOAD: f64 = 15e-6;
+
+/// One byte shifted on the bus, times in seconds
+#[derive(Clone, Copy, Debug)]
+pub struct Byte {
+    pub mosi: u8,
+    pub miso: u8,
+    /// first SCLK edge of the byte
+    pub first_edge: f64,
+    /// last SCLK edge (the sampling edge of the last bit)
+    pub last_edge: f64,
+}
+
+/// Everything clocked while NCS was held low
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    /// NCS falling edge
+    pub select: f64,
+    /// NCS rising edge, `None` if the capture ended with NCS low
+    pub deselect: Option<f64>,
+    pub bytes: Vec<Byte>,
+    /// Number of trailing bits not making up a full byte
+    pub partial_bits: u8,
+    /// Shortest time between two SCLK edges
+    pub min_half_period: Option<f64>,
+}
+
+/// Interpretation of a transaction in terms of the PMW3389 protocol
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    /// NCS toggled without any clocks, resets the serial port
+    Reset,
+    Read { addr: u8, data: Vec<u8> },
+    Write { addr: u8, data: Vec<u8> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Violation {
+    /// index of the offending transaction
+    pub index: usize,
+    pub what: &'static str,
+    pub actual: f64,
+    pub limit: f64,
+}
+
+/// Splits the capture into NCS framed transactions
+///
+/// `mode` is the SPI mode (0..3), the PMW3389 uses mode 3.
+pub fn transactions(samples: &[Sample], mode: u8) -> Vec<Transaction> {
+    // modes 0 and 3 sample on the rising edge, 1 and 2 on the falling edge
+    let sample_on_rising = mode == 0 || mode == 3;
+    let cpha = mode == 1 || mode == 3;
+
+    let mut transactions = Vec::new();
+    let mut current: Option<Transaction> = None;
+    let (mut bits, mut mosi, mut miso) = (0u8, 0u8, 0u8);
+    let (mut first_edge, mut last_edge) = (0.0, None::<f64>);
+    let mut started = false;
+
+    for w in samples.windows(2) {
+        let (prev, s) = (w[0], w[1]);
+
+        if prev.ncs && !s.ncs {
+            current = Some(Transaction {
+                select: s.time,
+                deselect: None,
+                bytes: Vec::new(),
+                partial_bits: 0,
+                min_half_period: None,
+            });
+            bits = 0;
+            started = false;
+            last_edge = None;
+        }
+
+        if let Some(tr) = current.as_mut() {
+            if s.sck != prev.sck && !s.ncs {
+                if let Some(last) = last_edge {
+                    let half = s.time - last;
+                    tr.min_half_period = Some(tr.min_half_period.map_or(half, |m| m.min(half)));
+                }
+                last_edge = Some(s.time);
+
+                let sampling = s.sck == sample_on_rising;
+                if !started && (cpha || sampling) {
+                    // first edge of a byte, the launch edge if CPHA = 1
+                    first_edge = s.time;
+                    started = true;
+                }
+
+                if sampling {
+                    mosi = mosi << 1 | s.mosi as u8;
+                    miso = miso << 1 | s.miso as u8;
+                    bits += 1;
+                    if bits == 8 {
+                        tr.bytes.push(Byte {
+                            mosi,
+                            miso,
+                            first_edge,
+                            last_edge: s.time,
+                        });
+                        bits = 0;
+                        started = false;
+                    }
+                }
+            }
+
+            if !prev.ncs && s.ncs {
+                tr.deselect = Some(s.time);
+                tr.partial_bits = bits;
+                transactions.push(current.take().unwrap());
+            }
+        }
+    }
+
+    if let Some(mut tr) = current {
+        tr.partial_bits = bits;
+        transactions.push(tr);
+    }
+
+    transactions
+}
+
+impl Transaction {
+    pub fn access(&self) -> Access {
+        match self.bytes.split_first() {
+            None => Access::Reset,
+            Some((first, rest)) => {
+                let addr = first.mosi & 0x7f;
+                if first.mosi & 0x80 != 0 {
+                    Access::Write {
+                        addr,
+                        data: rest.iter().map(|b| b.mosi).collect(),
+                    }
+                } else {
+                    Access::Read {
+                        addr,
+                        data: rest.iter().map(|b| b.miso).collect(),
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_write(&self) -> bool {
+        self.bytes.first().is_some_and(|b| b.mosi & 0x80 != 0)
+    }
+}
+
+/// Checks the transactions against the datasheet timing
+pub fn check(transactions: &[Transaction]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut prev: Option<&Transaction> = None;
+
+    let mut check = |index, what, actual: f64, limit: f64| {
+        if actual < limit {
+            violations.push(Violation {
+                index,
+                what,
+                actual,
+                limit,
+            });
+        }
+    };
+
+    for (index, tr) in transactions.iter().enumerate() {
+        let (first, last) = match (tr.bytes.first(), tr.bytes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+
+        check(index, "tNCS-SCLK", first.first_edge - tr.select, T_NCS_SCLK);
+
+        if let Some(half) = tr.min_half_period {
+            check(index, "tSCLK/2", half, T_SCLK_HALF);
+        }
+
+        if let Some(deselect) = tr.deselect {
+            if tr.is_write() {
+                check(index, "tSCLK-NCS(write)", deselect - last.last_edge, T_SCLK_NCS_WRITE);
+            } else {
+                check(index, "tSCLK-NCS(read)", deselect - last.last_edge, T_SCLK_NCS_READ);
+            }
+        }
+
+        match tr.access() {
+            Access::Read { addr, .. } if tr.bytes.len() > 1 => {
+                let (what, limit) = if addr == Register::MotionBurst.addr() {
+                    ("tSRAD_MOTBR", T_SRAD_MOTBR)
+                } else {
+                    ("tSRAD", T_SRAD)
+                };
+                check(index, what, tr.bytes[1].first_edge - first.last_edge, limit);
+            }
+            Access::Write { addr, .. } if addr == Register::SROMLoadBurst.addr() => {
+                for w in tr.bytes.windows(2) {
+                    check(index, "tLOAD", w[1].first_edge - w[0].last_edge, T_LOAD);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(p) = prev {
+            let p_last = p.bytes.last().unwrap();
+            let gap = first.first_edge - p_last.last_edge;
+            if p.is_write() {
+                check(index, "tSWW/tSWR", gap, T_SWW);
+            } else {
+                check(index, "tSRW/tSRR", gap, T_SRW);
+                if p.bytes[0].mosi == Register::MotionBurst.addr() {
+                    if let Some(deselect) = p.deselect {
+                        check(index, "tBEXIT", tr.select - deselect, T_BEXIT);
+                    }
+                }
+            }
+        }
+
+        prev = Some(tr);
+    }
+
+    violations
+}
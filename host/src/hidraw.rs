@@ -0,0 +1,184 @@
+//! Access to the mouse configuration over Linux hidraw
+//!
+//! Feature reports are exchanged by the HIDIOCSFEATURE/HIDIOCGFEATURE
+//! ioctls, no driver or library needed. The device needs read/write
+//! access, e.g., by a udev rule for the VID/PID.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::config_proto::{self, Command, Response, Status};
+use crate::usb_env;
+
+// Attempts to read a pending response, a `Save` (sector erase) takes up
+// to 4 s
+const RETRIES: u32 = 500;
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+// A VID/PID from the environment, as parsed by `build.rs`
+fn id_from_env(var: &str, default: u16) -> io::Result<u16> {
+    let s = match env::var(var) {
+        Ok(s) => s,
+        Err(_) => return Ok(default),
+    };
+    usb_env::parse_u16(&s).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}={} is not a u16", var, s),
+        )
+    })
+}
+
+// linux/hidraw.h, _IOC(_IOC_WRITE | _IOC_READ, 'H', nr, len)
+fn ioc_rw(nr: u64, len: usize) -> u64 {
+    3 << 30 | (len as u64) << 16 | (b'H' as u64) << 8 | nr
+}
+
+fn hidiocsfeature(len: usize) -> u64 {
+    ioc_rw(0x06, len)
+}
+
+fn hidiocgfeature(len: usize) -> u64 {
+    ioc_rw(0x07, len)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The device answered with an error status
+    Status(Status),
+    /// The response is to another command
+    Mismatch(u8),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Status(Status::BadVersion) => write!(
+                f,
+                "protocol version mismatch, tool is version {}",
+                config_proto::VERSION
+            ),
+            Error::Status(status) => write!(f, "device error {:?}", status),
+            Error::Mismatch(id) => write!(f, "response to command 0x{:02x}", id),
+        }
+    }
+}
+
+pub struct Hidraw {
+    file: File,
+}
+
+impl Hidraw {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Hidraw { file })
+    }
+
+    /// The hidraw nodes of the mouse (mouse and keyboard interface), by
+    /// `USB_VID`/`USB_PID` if set as for the firmware build
+    pub fn find() -> io::Result<Vec<PathBuf>> {
+        Self::find_in(Path::new("/sys/class/hidraw"))
+    }
+
+    /// As `find`, among the nodes of the sysfs class directory `class`
+    ///
+    /// Nodes without a readable `device/uevent` are skipped.
+    pub fn find_in(class: &Path) -> io::Result<Vec<PathBuf>> {
+        // HID_ID=0003:0000C410:00000000
+        let id = format!(
+            "HID_ID=0003:{:08X}:{:08X}",
+            id_from_env("USB_VID", usb_env::VID)?,
+            id_from_env("USB_PID", usb_env::PID)?
+        );
+        let mut found = vec![];
+        for entry in fs::read_dir(class)? {
+            let entry = entry?;
+            let uevent = match fs::read_to_string(entry.path().join("device/uevent")) {
+                Ok(uevent) => uevent,
+                Err(_) => continue,
+            };
+            if uevent.lines().any(|l| l == id) {
+                found.push(Path::new("/dev").join(entry.file_name()));
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+
+    /// Sends a feature report, `data[0]` being the report ID
+    pub fn set_feature(&self, data: &[u8]) -> io::Result<()> {
+        let r = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                hidiocsfeature(data.len()) as _,
+                data.as_ptr(),
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Reads a feature report, `buf[0]` being the report ID
+    pub fn get_feature(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let r = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                hidiocgfeature(buf.len()) as _,
+                buf.as_mut_ptr(),
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r as usize)
+    }
+
+    /// Sends a command, and reads back the response
+    pub fn command(&self, command: &Command) -> Result<Response, Error> {
+        self.set_feature(&request(command))?;
+
+        for _ in 0..RETRIES {
+            let mut report = [0; config_proto::REPORT_SIZE + 1];
+            report[0] = config_proto::REPORT_ID;
+            let n = self.get_feature(&mut report)?;
+            match response(command, &report[..n]) {
+                Err(Error::Status(Status::Pending)) => thread::sleep(RETRY_DELAY),
+                result => return result,
+            }
+        }
+        Err(Error::Status(Status::Pending))
+    }
+}
+
+/// The feature report of a request, with the report ID
+pub fn request(command: &Command) -> [u8; config_proto::REPORT_SIZE + 1] {
+    let mut report = [0; config_proto::REPORT_SIZE + 1];
+    report[0] = config_proto::REPORT_ID;
+    report[1..].copy_from_slice(&command.encode());
+    report
+}
+
+/// The response to `command` in a feature report (with the report ID) as
+/// read, `Status::Pending` if to be read again
+pub fn response(command: &Command, report: &[u8]) -> Result<Response, Error> {
+    match config_proto::decode_response(report.get(1..).unwrap_or(&[])) {
+        (_, Err(status)) => Err(Error::Status(status)),
+        (id, Ok(_)) if id != command.id() => Err(Error::Mismatch(id)),
+        (_, Ok(response)) => Ok(response),
+    }
+}
@@ -0,0 +1,60 @@
+//! Host side tools for the PMW3389 mouse
+//!
+//! Shares target-independent modules (like the PMW3389 register map)
+//! with the firmware crate.
+
+pub mod capture;
+pub mod decode;
+pub mod hidraw;
+pub mod sim;
+
+#[path = "../../src/pmw3389/register.rs"]
+pub mod register;
+
+// The configuration protocol, and the button map it carries
+#[path = "../../src/buttons.rs"]
+pub mod buttons;
+#[path = "../../src/config_proto.rs"]
+pub mod config_proto;
+// and the console, carrying out the same commands
+#[path = "../../src/console.rs"]
+pub mod console;
+#[path = "../../src/keyboard/report.rs"]
+pub mod keyboard_report;
+// The USB ids, as set for the firmware build
+#[path = "../../src/usb_id/env.rs"]
+pub mod usb_env;
+
+// Motion processing, and the scroll wheel
+#[path = "../../src/encoder.rs"]
+pub mod encoder;
+#[path = "../../src/odometry.rs"]
+pub mod odometry;
+#[path = "../../src/scaler.rs"]
+pub mod scaler;
+#[path = "../../src/scroll.rs"]
+pub mod scroll;
+#[path = "../../src/velocity.rs"]
+pub mod velocity;
+
+// Report rate statistics, as in the diagnostics, and USB suspend
+#[path = "../../src/report_stats.rs"]
+pub mod report_stats;
+#[path = "../../src/suspend.rs"]
+pub mod suspend;
+
+// The drivers of the emulated SPI path, run against `sim`
+#[path = "../../src/pmw3389e.rs"]
+pub mod pmw3389e;
+#[path = "../../src/sc18is602.rs"]
+pub mod sc18is602;
+
+// the register map, where the shared drivers expect it
+pub mod pmw3389 {
+    pub use crate::register::Register;
+}
+
+// the keyboard report, where the button map expects it
+pub mod keyboard {
+    pub use crate::keyboard_report::*;
+}
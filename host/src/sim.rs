@@ -0,0 +1,69 @@
+//! Software models of the hardware on the emulated SPI path
+//!
+//! `Sc18is602` models the I2C to SPI bridge (implementing the embedded-hal
+//! I2C traits), forwarding the SPI traffic to the `SpiSlave` devices on
+//! its slave select lines, e.g., the `Pmw3389` sensor model. Together with
+//! the simulated `Delay`, the firmware drivers (`sc18is602`, `pmw3389e`)
+//! run unchanged on the host.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+mod pmw3389;
+mod sc18is602;
+
+pub use self::pmw3389::{Pmw3389, SROM_ID, SROM_SIZE};
+pub use self::sc18is602::{Error, IntPin, Sc18is602};
+
+/// A SPI device model
+pub trait SpiSlave {
+    /// Called on a change of the (active low) slave select line
+    fn select(&mut self, selected: bool);
+
+    /// Exchanges one byte while selected, MOSI in, MISO out
+    fn transfer(&mut self, mosi: u8) -> u8;
+}
+
+/// A device model shared with the test bench, e.g., for injecting motion
+impl<T: SpiSlave> SpiSlave for Rc<RefCell<T>> {
+    fn select(&mut self, selected: bool) {
+        self.borrow_mut().select(selected)
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        self.borrow_mut().transfer(mosi)
+    }
+}
+
+/// Simulated time, advanced by the delays of the drivers
+///
+/// Clones share the same clock.
+#[derive(Clone, Default)]
+pub struct Delay {
+    us: Rc<Cell<u64>>,
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time passed in delays, in us
+    pub fn elapsed_us(&self) -> u64 {
+        self.us.get()
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        self.us.set(self.us.get() + us as u64);
+    }
+}
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_us(ms * 1000)
+    }
+}
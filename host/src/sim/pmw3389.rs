@@ -0,0 +1,206 @@
+//! PMW3389 sensor model
+//!
+//! Register reads and writes, the motion burst and the SROM download, as
+//! used by the drivers. Motion is injected by `move_by`, and reported as
+//! by the sensor: reading Motion (or the burst) latches the deltas.
+//! Timing is not checked (see `decode` for checking captures).
+
+use crate::register::Register;
+
+use super::SpiSlave;
+
+/// Size of the SROM (firmware) image
+pub const SROM_SIZE: usize = 4094;
+
+/// SROM id reported after a complete download
+pub const SROM_ID: u8 = 0x04;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    // waiting for the address byte
+    Address,
+    Read(u8),
+    Write(u8),
+    Burst(usize),
+    SromLoad,
+}
+
+pub struct Pmw3389 {
+    regs: [u8; 128],
+    // motion not yet latched
+    dx: i32,
+    dy: i32,
+    burst: [u8; 12],
+    srom: Vec<u8>,
+    state: State,
+}
+
+impl Pmw3389 {
+    /// Creates a sensor, in its power up state
+    pub fn new() -> Self {
+        let mut pmw = Pmw3389 {
+            regs: [0; 128],
+            dx: 0,
+            dy: 0,
+            burst: [0; 12],
+            srom: Vec::new(),
+            state: State::Address,
+        };
+        pmw.reset();
+        pmw
+    }
+
+    fn reset(&mut self) {
+        self.regs = [0; 128];
+        self.regs[Register::ProductId.addr() as usize] = 0x47;
+        self.regs[Register::RevisionId.addr() as usize] = 0x01;
+        self.regs[Register::InverseProductID.addr() as usize] = 0xb8;
+        self.regs[Register::SQUAL.addr() as usize] = 0x40;
+        // 5000 CPI
+        self.regs[Register::ResolutionL.addr() as usize] = 0x31;
+        self.dx = 0;
+        self.dy = 0;
+        self.srom.clear();
+    }
+
+    /// Moves the sensor, in counts
+    pub fn move_by(&mut self, dx: i32, dy: i32) {
+        self.dx += dx;
+        self.dy += dy;
+    }
+
+    pub fn register(&self, reg: Register) -> u8 {
+        self.regs[reg.addr() as usize]
+    }
+
+    /// The downloaded SROM image
+    pub fn srom(&self) -> &[u8] {
+        &self.srom
+    }
+
+    /// The resolution in counts per inch
+    pub fn cpi(&self) -> u32 {
+        let res = (self.register(Register::ResolutionH) as u32) << 8
+            | self.register(Register::ResolutionL) as u32;
+        (res + 1) * 50
+    }
+
+    // Latches the pending motion into Motion and DeltaX/Y
+    fn latch(&mut self) {
+        let dx = self.dx.max(i16::MIN as i32).min(i16::MAX as i32) as i16;
+        let dy = self.dy.max(i16::MIN as i32).min(i16::MAX as i32) as i16;
+        self.dx = 0;
+        self.dy = 0;
+
+        let motion = if dx != 0 || dy != 0 { 0x80 } else { 0x00 };
+        self.regs[Register::Motion.addr() as usize] = motion;
+        self.regs[Register::DeltaXL.addr() as usize] = dx as u8;
+        self.regs[Register::DeltaXH.addr() as usize] = (dx >> 8) as u8;
+        self.regs[Register::DeltaYL.addr() as usize] = dy as u8;
+        self.regs[Register::DeltaYH.addr() as usize] = (dy >> 8) as u8;
+    }
+
+    fn read(&mut self, addr: u8) -> u8 {
+        if addr == Register::Motion.addr() {
+            self.latch();
+        }
+        self.regs[addr as usize]
+    }
+
+    fn write(&mut self, addr: u8, data: u8) {
+        match Register::from_addr(addr) {
+            Some(Register::PowerUpReset) if data == 0x5a => self.reset(),
+            // writing Motion clears the latched motion
+            Some(Register::Motion) => {
+                for reg in &[
+                    Register::Motion,
+                    Register::DeltaXL,
+                    Register::DeltaXH,
+                    Register::DeltaYL,
+                    Register::DeltaYH,
+                ] {
+                    self.regs[reg.addr() as usize] = 0;
+                }
+            }
+            Some(Register::ProductId)
+            | Some(Register::RevisionId)
+            | Some(Register::InverseProductID)
+            | Some(Register::SROMId) => {}
+            _ => self.regs[addr as usize] = data,
+        }
+    }
+
+    fn load_burst(&mut self) {
+        self.latch();
+        let reg = |r: Register| self.regs[r.addr() as usize];
+        self.burst = [
+            reg(Register::Motion),
+            reg(Register::Observation),
+            reg(Register::DeltaXL),
+            reg(Register::DeltaXH),
+            reg(Register::DeltaYL),
+            reg(Register::DeltaYH),
+            reg(Register::SQUAL),
+            reg(Register::RawDataSum),
+            reg(Register::MaximumRawdata),
+            reg(Register::MinimumRawdata),
+            reg(Register::ShutterUpper),
+            reg(Register::ShutterLower),
+        ];
+    }
+}
+
+impl Default for Pmw3389 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpiSlave for Pmw3389 {
+    fn select(&mut self, selected: bool) {
+        if !selected && self.state == State::SromLoad {
+            let id = if self.srom.len() == SROM_SIZE { SROM_ID } else { 0 };
+            self.regs[Register::SROMId.addr() as usize] = id;
+        }
+        self.state = State::Address;
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        match self.state {
+            State::Address => {
+                let addr = mosi & 0x7f;
+                self.state = if mosi & 0x80 == 0 {
+                    if addr == Register::MotionBurst.addr() {
+                        self.load_burst();
+                        State::Burst(0)
+                    } else {
+                        State::Read(addr)
+                    }
+                } else if addr == Register::SROMLoadBurst.addr() {
+                    self.srom.clear();
+                    State::SromLoad
+                } else {
+                    State::Write(addr)
+                };
+                0
+            }
+            State::Read(addr) => {
+                self.state = State::Address;
+                self.read(addr)
+            }
+            State::Write(addr) => {
+                self.state = State::Address;
+                self.write(addr, mosi);
+                0
+            }
+            State::Burst(i) => {
+                self.state = State::Burst(i + 1);
+                self.burst.get(i).copied().unwrap_or(0)
+            }
+            State::SromLoad => {
+                self.srom.push(mosi);
+                0
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! SC18IS602 I2C to SPI bridge model
+//!
+//! Interprets the function ids written over I2C (see the driver in
+//! `src/sc18is602.rs`). SpiReadWrite transfers are instantaneous, unless
+//! the bridge is set busy for a number of accesses (see `set_busy`), the
+//! INT output is asserted when done and released by ClearInterrupt.
+//!
+//! A slave select line is low (selected) while asserted by a SpiReadWrite
+//! (hardware managed), or while driven low as a GPIO. A transfer selecting
+//! a line enabled as GPIO is ignored, as by the bridge.
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::{blocking::i2c, digital::v2::InputPin};
+
+use super::SpiSlave;
+
+const BUFFER_SIZE: usize = 200;
+
+/// I2C bus errors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Address or data not acknowledged
+    Nack,
+}
+
+// What an I2C read returns
+enum Read {
+    Buffer,
+    Gpio,
+}
+
+struct State {
+    addr: u8,
+    config: u8,
+    gpio_enable: u8,
+    gpio_config: u8,
+    gpio_output: u8,
+    buffer: Vec<u8>,
+    read: Read,
+    int: bool,
+    idle: bool,
+    // accesses a transfer keeps the bridge busy, and those left
+    busy_for: u32,
+    busy: u32,
+    nacks: u32,
+    // lines currently low
+    low: u8,
+    devices: [Option<Box<dyn SpiSlave>>; 4],
+}
+
+/// The bridge, clones share the same model
+#[derive(Clone)]
+pub struct Sc18is602(Rc<RefCell<State>>);
+
+impl Sc18is602 {
+    /// Creates a bridge, `pins` being the A2:A0 pin setting
+    pub fn new(pins: u8) -> Self {
+        Sc18is602(Rc::new(RefCell::new(State {
+            addr: 0x28 | (pins & 0x7),
+            config: 0,
+            gpio_enable: 0,
+            gpio_config: 0,
+            gpio_output: 0,
+            buffer: Vec::new(),
+            read: Read::Buffer,
+            int: false,
+            idle: false,
+            busy_for: 0,
+            busy: 0,
+            nacks: 0,
+            low: 0,
+            devices: [None, None, None, None],
+        })))
+    }
+
+    /// Attaches a device to SSn
+    pub fn attach<D: SpiSlave + 'static>(&self, n: u8, device: D) {
+        self.0.borrow_mut().devices[n as usize] = Some(Box::new(device));
+    }
+
+    /// The (active low) INT output
+    pub fn int_pin(&self) -> IntPin {
+        IntPin(self.0.clone())
+    }
+
+    /// The SpiConfigure setting
+    pub fn config(&self) -> u8 {
+        self.0.borrow().config
+    }
+
+    /// The GpioEnable, GpioConfigure and GpioWrite settings
+    pub fn gpio(&self) -> (u8, u8, u8) {
+        let s = self.0.borrow();
+        (s.gpio_enable, s.gpio_config, s.gpio_output)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.0.borrow().idle
+    }
+
+    /// Keeps the bridge busy after each transfer, for `accesses` I2C
+    /// accesses (NACKed) or INT samples, as a transfer takes time
+    pub fn set_busy(&self, accesses: u32) {
+        self.0.borrow_mut().busy_for = accesses;
+    }
+
+    /// The accesses NACKed while busy
+    pub fn nacks(&self) -> u32 {
+        self.0.borrow().nacks
+    }
+}
+
+impl State {
+    // Counts down a busy transfer, true while still busy
+    fn tick(&mut self) -> bool {
+        if self.busy == 0 {
+            return false;
+        }
+        self.busy -= 1;
+        if self.busy == 0 {
+            self.int = true;
+        }
+        true
+    }
+
+    // Address acknowledged, unless busy
+    fn access(&mut self, addr: u8) -> Result<(), Error> {
+        if addr != self.addr {
+            return Err(Error::Nack);
+        }
+        if self.tick() {
+            self.nacks += 1;
+            return Err(Error::Nack);
+        }
+        self.idle = false;
+        Ok(())
+    }
+
+    // Lines driven low as GPIO
+    fn gpio_low(&self) -> u8 {
+        (0..4)
+            .filter(|n| self.gpio_enable & 1 << n != 0)
+            // input only lines float (pulled up)
+            .filter(|n| (self.gpio_config >> (2 * n)) & 0b11 != 0b10)
+            .filter(|n| self.gpio_output & 1 << n == 0)
+            .fold(0, |low, n| low | 1 << n)
+    }
+
+    // Updates the line levels, `hw` being the lines asserted by a transfer
+    fn update_lines(&mut self, hw: u8) {
+        let low = self.gpio_low() | (hw & !self.gpio_enable & 0x0f);
+        for (n, device) in self.devices.iter_mut().enumerate() {
+            let mask = 1 << n;
+            if let Some(device) = device {
+                if low & mask != self.low & mask {
+                    device.select(low & mask != 0);
+                }
+            }
+        }
+        self.low = low;
+    }
+
+    fn spi_transfer(&mut self, ss: u8, data: &[u8]) {
+        if ss & self.gpio_enable != 0 {
+            return;
+        }
+
+        self.update_lines(ss);
+        let lsb_first = self.config & 1 << 5 != 0;
+        let low = self.low;
+        self.buffer = data
+            .iter()
+            .map(|&mosi| {
+                let mosi = if lsb_first { mosi.reverse_bits() } else { mosi };
+                // the MISO line is pulled up, and wired-and between devices
+                let miso = self
+                    .devices
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(n, _)| low & 1 << n != 0)
+                    .filter_map(|(_, device)| device.as_mut())
+                    .fold(0xff, |miso, device| miso & device.transfer(mosi));
+                if lsb_first {
+                    miso.reverse_bits()
+                } else {
+                    miso
+                }
+            })
+            .collect();
+        self.update_lines(0);
+
+        self.read = Read::Buffer;
+        self.busy = self.busy_for;
+        self.int = self.busy == 0;
+    }
+}
+
+impl i2c::Write for Sc18is602 {
+    type Error = Error;
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        let mut s = self.0.borrow_mut();
+        s.access(addr)?;
+
+        let (&function, data) = match bytes.split_first() {
+            Some(split) => split,
+            // address only, e.g., polling for completion
+            None => return Ok(()),
+        };
+        if data.len() > BUFFER_SIZE {
+            return Err(Error::Nack);
+        }
+
+        let arg = data.first().copied().unwrap_or(0);
+        match function {
+            0x01..=0x0f => s.spi_transfer(function, data),
+            0xf0 => s.config = arg,
+            0xf1 => s.int = false,
+            0xf2 => s.idle = true,
+            0xf4 => {
+                s.gpio_output = arg & 0x0f;
+                s.update_lines(0);
+            }
+            0xf5 => s.read = Read::Gpio,
+            0xf6 => {
+                s.gpio_enable = arg & 0x0f;
+                s.update_lines(0);
+            }
+            0xf7 => {
+                s.gpio_config = arg;
+                s.update_lines(0);
+            }
+            // reserved, ignored
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl i2c::Read for Sc18is602 {
+    type Error = Error;
+
+    fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut s = self.0.borrow_mut();
+        s.access(addr)?;
+
+        match s.read {
+            Read::Buffer => {
+                for (i, b) in buffer.iter_mut().enumerate() {
+                    *b = s.buffer.get(i).copied().unwrap_or(0);
+                }
+            }
+            Read::Gpio => {
+                if let Some(b) = buffer.first_mut() {
+                    *b = !s.low & 0x0f;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The INT output of the bridge, low when a transfer completed
+pub struct IntPin(Rc<RefCell<State>>);
+
+impl InputPin for IntPin {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        self.is_low().map(|low| !low)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        let mut s = self.0.borrow_mut();
+        s.tick();
+        Ok(s.int)
+    }
+}
@@ -0,0 +1,78 @@
+//! Button mapping, and the actions rejected beyond the reports
+
+use host::buttons::{Action, ButtonMap, InvalidAction, MOUSE_BUTTONS};
+use host::config_proto::{Command, Status, VERSION};
+use host::keyboard::{KEY_MAX, MOD_LCTRL};
+
+#[test]
+fn map() {
+    let mut map = ButtonMap::default();
+    let copy = Action::Key {
+        modifiers: MOD_LCTRL,
+        key: 0x06,
+    };
+    map.set(1, Action::Mouse(4)).unwrap();
+    map.set(4, copy).unwrap();
+    map.set(2, Action::Scroll).unwrap();
+
+    // left, right (as forward) and M1 (as Ctrl+C) pressed
+    let (buttons, keys) = map.map(0b1_0011);
+    assert_eq!(buttons, 0b1_0001);
+    assert_eq!(keys.modifiers, MOD_LCTRL);
+    assert_eq!(keys.keys[0], 0x06);
+    assert!(map.scrolling(0b0_0100));
+    assert!(!map.scrolling(0b1_1011));
+}
+
+#[test]
+fn set_rejects_beyond_the_reports() {
+    let mut map = ButtonMap::default();
+    for action in [
+        Action::Mouse(MOUSE_BUTTONS),
+        // would overflow the shift into the report
+        Action::Mouse(8),
+        Action::Mouse(u8::MAX),
+        Action::Key {
+            modifiers: 0,
+            key: KEY_MAX + 1,
+        },
+    ]
+    .iter()
+    {
+        assert_eq!(map.set(0, *action), Err(InvalidAction(*action)));
+    }
+    // left unchanged, and pressing it still works
+    assert_eq!(map, ButtonMap::default());
+    assert_eq!(map.map(0b1).0, 0b1);
+}
+
+#[test]
+fn config_rejects_beyond_the_reports() {
+    let mut buf = Command::SetButtonMap(ButtonMap::default()).encode();
+    assert_eq!(buf[0], VERSION);
+    // the first button, as mouse button 8
+    buf[2..5].copy_from_slice(&[1, 8, 0]);
+    assert_eq!(Command::decode(&buf), Err(Status::BadArgument));
+}
+
+#[test]
+fn clear_keys() {
+    let mut map = ButtonMap::default();
+    assert!(!map.has_keys());
+    map.set(
+        3,
+        Action::Key {
+            modifiers: MOD_LCTRL,
+            key: 0x19,
+        },
+    )
+    .unwrap();
+    map.set(2, Action::Scroll).unwrap();
+    assert!(map.has_keys());
+
+    // without a keyboard, back as the mouse button, the rest kept
+    map.clear_keys();
+    assert!(!map.has_keys());
+    assert_eq!(map.action(3), Action::Mouse(3));
+    assert_eq!(map.action(2), Action::Scroll);
+}
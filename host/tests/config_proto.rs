@@ -0,0 +1,284 @@
+//! The configuration protocol, requests, responses and the stored
+//! configuration
+
+use host::buttons::{Action, ButtonMap, BUTTONS};
+use host::config_proto::{
+    decode_response, encode_response, Command, Config, Diagnostics, LedMode, Lift, Profiles,
+    Response, Status, CPI_MAX, CPI_MIN, CPI_STEP, PROFILES, REPORT_SIZE, VERSION,
+};
+use host::keyboard::KEY_MAX;
+
+// Command IDs
+const GET_VERSION: u8 = 0x01;
+const GET_CPI: u8 = 0x02;
+const SET_CPI: u8 = 0x03;
+const GET_BUTTON_MAP: u8 = 0x04;
+const SET_BUTTON_MAP: u8 = 0x05;
+const GET_POLL_INTERVAL: u8 = 0x06;
+const SET_POLL_INTERVAL: u8 = 0x07;
+const GET_LIFT: u8 = 0x08;
+const SET_LIFT: u8 = 0x09;
+const GET_LED: u8 = 0x0a;
+const SET_LED: u8 = 0x0b;
+const SAVE: u8 = 0x0c;
+const GET_PROFILES: u8 = 0x0d;
+const SET_PROFILES: u8 = 0x0e;
+const GET_DIAGNOSTICS: u8 = 0x0f;
+
+// Encoded size of an `Action`, and of `Profiles`
+const ACTION_SIZE: usize = 3;
+const PROFILES_SIZE: usize = 1 + 2 * PROFILES;
+
+// Two's complement of the byte sum, as of a stored configuration
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg()
+}
+
+fn map() -> ButtonMap {
+    let mut map = ButtonMap::default();
+    map.set(0, Action::None).unwrap();
+    map.set(2, Action::Scroll).unwrap();
+    map.set(
+        3,
+        Action::Key {
+            modifiers: 0x01,
+            key: KEY_MAX,
+        },
+    )
+    .unwrap();
+    map
+}
+
+fn profiles() -> Profiles {
+    Profiles {
+        active: 3,
+        cpi: [CPI_MIN, 400, 12000, CPI_MAX],
+    }
+}
+
+// A request with the payload `payload`
+fn request(id: u8, payload: &[u8]) -> [u8; REPORT_SIZE] {
+    let mut buf = [0; REPORT_SIZE];
+    buf[0] = VERSION;
+    buf[1] = id;
+    buf[2..2 + payload.len()].copy_from_slice(payload);
+    buf
+}
+
+// The command IDs above are those of the protocol
+#[test]
+fn ids() {
+    let ids = [
+        (Command::GetVersion, GET_VERSION),
+        (Command::GetCpi, GET_CPI),
+        (Command::SetCpi(CPI_MIN), SET_CPI),
+        (Command::GetButtonMap, GET_BUTTON_MAP),
+        (Command::SetButtonMap(map()), SET_BUTTON_MAP),
+        (Command::GetPollInterval, GET_POLL_INTERVAL),
+        (Command::SetPollInterval(1), SET_POLL_INTERVAL),
+        (Command::GetLift, GET_LIFT),
+        (Command::SetLift(Lift::Mm2), SET_LIFT),
+        (Command::GetLed, GET_LED),
+        (Command::SetLed(LedMode::Off), SET_LED),
+        (Command::Save, SAVE),
+        (Command::GetProfiles, GET_PROFILES),
+        (Command::SetProfiles(profiles()), SET_PROFILES),
+        (Command::GetDiagnostics, GET_DIAGNOSTICS),
+    ];
+    for (command, id) in ids.iter() {
+        assert_eq!(command.id(), *id, "{:?}", command);
+    }
+}
+
+#[test]
+fn commands() {
+    let commands = [
+        Command::GetVersion,
+        Command::GetCpi,
+        Command::SetCpi(CPI_MIN),
+        Command::SetCpi(CPI_MAX),
+        Command::GetProfiles,
+        Command::SetProfiles(profiles()),
+        Command::GetButtonMap,
+        Command::SetButtonMap(map()),
+        Command::GetPollInterval,
+        Command::SetPollInterval(8),
+        Command::GetLift,
+        Command::SetLift(Lift::Mm3),
+        Command::GetLed,
+        Command::SetLed(LedMode::Buttons),
+        Command::Save,
+        Command::GetDiagnostics,
+    ];
+    for command in commands.iter() {
+        let buf = command.encode();
+        assert_eq!(buf[1], command.id());
+        assert_eq!(Command::decode(&buf), Ok(*command));
+    }
+}
+
+#[test]
+fn responses() {
+    let diagnostics = Diagnostics {
+        connected: true,
+        product_id: 0x47,
+        srom_id: 0xe8,
+        squal: 0x30,
+        shutter: 0x1234,
+        report_rate: 1000,
+        missed: 0x0102_0304,
+        empty: u32::MAX,
+    };
+    let responses = [
+        (GET_VERSION, Response::Version(VERSION)),
+        (GET_CPI, Response::Cpi(1600)),
+        (GET_PROFILES, Response::Profiles(profiles())),
+        (GET_BUTTON_MAP, Response::ButtonMap(map())),
+        (GET_POLL_INTERVAL, Response::PollInterval(2)),
+        (GET_LIFT, Response::Lift(Lift::Mm3)),
+        (GET_LED, Response::Led(LedMode::On)),
+        (GET_DIAGNOSTICS, Response::Diagnostics(diagnostics)),
+        (SET_CPI, Response::Done),
+        (SAVE, Response::Done),
+    ];
+    for (id, response) in responses.iter() {
+        let buf = encode_response(*id, &Ok(*response));
+        assert_eq!(decode_response(&buf), (*id, Ok(*response)));
+    }
+}
+
+#[test]
+fn failed_responses() {
+    for status in [
+        Status::Pending,
+        Status::BadVersion,
+        Status::UnknownCommand,
+        Status::BadArgument,
+        Status::Failed,
+    ]
+    .iter()
+    {
+        let buf = encode_response(SAVE, &Err(*status));
+        assert_eq!(decode_response(&buf), (SAVE, Err(*status)));
+    }
+}
+
+#[test]
+fn config() {
+    let config = Config {
+        profiles: profiles(),
+        buttons: map(),
+        poll_ms: 4,
+        lift: Lift::Mm3,
+        led: LedMode::Off,
+    };
+    assert_eq!(Config::from_bytes(&config.to_bytes()), Some(config));
+    let config = Config::default();
+    assert_eq!(Config::from_bytes(&config.to_bytes()), Some(config));
+}
+
+#[test]
+fn bad_version() {
+    let mut buf = Command::GetCpi.encode();
+    buf[0] = VERSION + 1;
+    assert_eq!(Command::decode(&buf), Err(Status::BadVersion));
+
+    let mut buf = encode_response(GET_CPI, &Ok(Response::Cpi(800)));
+    buf[0] = VERSION - 1;
+    assert_eq!(decode_response(&buf), (0, Err(Status::BadVersion)));
+
+    // with a valid checksum
+    let mut buf = Config::default().to_bytes();
+    buf[2] = VERSION + 1;
+    buf[Config::SIZE - 1] = checksum(&buf[..Config::SIZE - 1]);
+    assert_eq!(Config::from_bytes(&buf), None);
+}
+
+#[test]
+fn bad_length() {
+    assert_eq!(Command::decode(&[]), Err(Status::BadVersion));
+    assert_eq!(Command::decode(&[VERSION]), Err(Status::BadVersion));
+    assert_eq!(
+        Command::decode(&[VERSION, SET_CPI, 0x20]),
+        Err(Status::BadArgument)
+    );
+    assert_eq!(
+        Command::decode(&[VERSION, SET_LIFT]),
+        Err(Status::BadArgument)
+    );
+    let buf = Command::SetProfiles(profiles()).encode();
+    assert_eq!(
+        Command::decode(&buf[..1 + PROFILES_SIZE]),
+        Err(Status::BadArgument)
+    );
+    let buf = Command::SetButtonMap(map()).encode();
+    assert_eq!(
+        Command::decode(&buf[..1 + BUTTONS * ACTION_SIZE]),
+        Err(Status::BadArgument)
+    );
+
+    assert_eq!(
+        decode_response(&[VERSION, GET_CPI]).1,
+        Err(Status::BadVersion)
+    );
+    let buf = encode_response(
+        GET_DIAGNOSTICS,
+        &Ok(Response::Diagnostics(Default::default())),
+    );
+    assert_eq!(decode_response(&buf[..18]).1, Err(Status::BadArgument));
+
+    let buf = Config::default().to_bytes();
+    assert_eq!(Config::from_bytes(&buf[..Config::SIZE - 1]), None);
+}
+
+#[test]
+fn bad_id() {
+    for id in [0x00, GET_DIAGNOSTICS + 1, 0xff].iter() {
+        assert_eq!(
+            Command::decode(&request(*id, &[])),
+            Err(Status::UnknownCommand)
+        );
+    }
+}
+
+#[test]
+fn bad_argument() {
+    let bad = [
+        request(SET_CPI, &(CPI_MIN - CPI_STEP).to_le_bytes()),
+        request(SET_CPI, &(CPI_MAX + CPI_STEP).to_le_bytes()),
+        request(SET_CPI, &(CPI_MIN + 1).to_le_bytes()),
+        request(SET_POLL_INTERVAL, &[0]),
+        request(SET_POLL_INTERVAL, &[3]),
+        request(SET_POLL_INTERVAL, &[10]),
+        request(SET_LIFT, &[2]),
+        request(SET_LED, &[3]),
+        // no such active profile, and a resolution out of range
+        request(SET_PROFILES, &[PROFILES as u8, 0x20, 0x03]),
+        request(SET_PROFILES, &[0, 0x00, 0x00]),
+        // a sixth (or ninth) mouse button, an unknown action and a key
+        // beyond the keyboard report
+        request(SET_BUTTON_MAP, &[1, 5, 0]),
+        request(SET_BUTTON_MAP, &[1, 8, 0]),
+        request(SET_BUTTON_MAP, &[1, 0xff, 0]),
+        request(SET_BUTTON_MAP, &[4, 0, 0]),
+        request(SET_BUTTON_MAP, &[2, 0, KEY_MAX + 1]),
+    ];
+    for buf in bad.iter() {
+        assert_eq!(
+            Command::decode(buf),
+            Err(Status::BadArgument),
+            "{:02x?}",
+            buf
+        );
+    }
+}
+
+#[test]
+fn corrupt_config() {
+    let mut buf = Config::default().to_bytes();
+    buf[12] ^= 0x01;
+    assert_eq!(Config::from_bytes(&buf), None);
+    assert_eq!(Config::from_bytes(&[0xff; Config::SIZE]), None);
+}
@@ -0,0 +1,137 @@
+//! Console line editing and parsing
+
+use host::config_proto::{Command, LedMode, Lift, Response, Status};
+use host::console::{Console, Input};
+
+// Types `data`, returns the lines carried out and the output
+fn type_in(console: &mut Console, data: &str) -> (Vec<Input>, String) {
+    let mut inputs = vec![];
+    console.receive(data.as_bytes(), |input, _| inputs.push(input));
+    let out = console.output();
+    let text = String::from_utf8(out.pending().to_vec()).unwrap();
+    out.consume(text.len());
+    (inputs, text)
+}
+
+fn console() -> Console {
+    let mut console = Console::new();
+    assert_eq!(type_in(&mut console, "").1, "> ");
+    console
+}
+
+#[test]
+fn echo_and_prompt() {
+    let mut console = console();
+    let (inputs, out) = type_in(&mut console, "version\r");
+    assert_eq!(inputs, vec![Input::Command(Command::GetVersion)]);
+    assert_eq!(out, "version\r\n> ");
+
+    // an empty line only prompts
+    assert_eq!(type_in(&mut console, "\r"), (vec![], "\r\n> ".into()));
+}
+
+#[test]
+fn line_endings() {
+    // CR LF is a single line end, LF alone also ends a line
+    let mut console = console();
+    let (inputs, _) = type_in(&mut console, "cpi\r\nlift\ndiag\r");
+    assert_eq!(
+        inputs,
+        vec![
+            Input::Command(Command::GetCpi),
+            Input::Command(Command::GetLift),
+            Input::Command(Command::GetDiagnostics),
+        ]
+    );
+}
+
+#[test]
+fn backspace() {
+    let mut console = console();
+    let (inputs, out) = type_in(&mut console, "cpx\x08i 8000\x7f\r");
+    assert_eq!(inputs, vec![Input::Command(Command::SetCpi(800))]);
+    assert_eq!(out, "cpx\x08 \x08i 8000\x08 \x08\r\n> ");
+
+    // nothing to erase
+    assert_eq!(type_in(&mut console, "\x08\x08").1, "");
+}
+
+#[test]
+fn control_characters_and_long_lines() {
+    let mut console = console();
+    let (inputs, _) = type_in(&mut console, "\x1bsave\t\r");
+    assert_eq!(inputs, vec![Input::Command(Command::Save)]);
+
+    // the line is cut at its 64 characters
+    let long = format!("led on{}\r", " ".repeat(100));
+    let (inputs, _) = type_in(&mut console, &long);
+    assert_eq!(inputs, vec![Input::Command(Command::SetLed(LedMode::On))]);
+}
+
+#[test]
+fn commands() {
+    let lines = [
+        ("cpi 0x640", Input::Command(Command::SetCpi(1600))),
+        ("poll 4", Input::Command(Command::SetPollInterval(4))),
+        ("lift 3", Input::Command(Command::SetLift(Lift::Mm3))),
+        (
+            "led buttons",
+            Input::Command(Command::SetLed(LedMode::Buttons)),
+        ),
+        ("  reg   0x02 ", Input::ReadRegister(0x02)),
+        ("reg 16 0x20", Input::WriteRegister(0x10, 0x20)),
+        ("selftest", Input::SelfTest),
+    ];
+    let mut console = console();
+    for (line, input) in lines.iter() {
+        let (inputs, _) = type_in(&mut console, &format!("{}\r", line));
+        assert_eq!(inputs, vec![*input], "{}", line);
+    }
+}
+
+#[test]
+fn rejected() {
+    let lines = [
+        // out of range, as for the feature report
+        ("cpi 75", "bad argument"),
+        ("cpi 0x10000", "bad argument"),
+        ("poll 3", "bad argument"),
+        ("poll 256", "bad argument"),
+        ("lift 4", "bad argument"),
+        ("led dim", "bad argument"),
+        ("cpi 800 1600 3200", "bad argument"),
+        ("reg 0x80", "bad register address"),
+        ("reg 0x10 0x100", "bad argument"),
+        ("reg", "bad argument"),
+        ("dpi 800", "unknown command, see help"),
+    ];
+    let mut console = console();
+    for (line, message) in lines.iter() {
+        let (inputs, out) = type_in(&mut console, &format!("{}\r", line));
+        assert_eq!(inputs, vec![], "{}", line);
+        assert_eq!(out, format!("{}\r\n{}\r\n> ", line, message));
+    }
+    let (_, out) = type_in(&mut console, "help\r");
+    assert!(out.contains("selftest"));
+}
+
+#[test]
+fn output() {
+    let mut console = console();
+    let out = console.output();
+    out.response(&Ok(Response::Done));
+    out.response(&Ok(Response::Cpi(800)));
+    out.response(&Err(Status::Failed));
+    out.register(0x02, 0x80);
+    out.self_test(0xbeef);
+    assert_eq!(
+        String::from_utf8(out.pending().to_vec()).unwrap(),
+        "ok\r\n800 cpi\r\nerror Failed\r\n0x02 Motion = 0x80\r\nsrom crc 0xbeef, passed\r\n"
+    );
+    // partially sent
+    out.consume(4);
+    assert_eq!(
+        out.pending(),
+        b"800 cpi\r\nerror Failed\r\n0x02 Motion = 0x80\r\nsrom crc 0xbeef, passed\r\n"
+    );
+}
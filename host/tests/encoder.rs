@@ -0,0 +1,102 @@
+//! Scroll wheel encoder decoding, on sampled and counted transitions
+
+use host::encoder::{Counter, Quadrature};
+
+// Forward, A leading B
+const FORWARD: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+const DEBOUNCE: u8 = 2;
+
+// Holds each state for `DEBOUNCE` samples
+fn turn(wheel: &mut Quadrature, states: &[(bool, bool)]) {
+    for &(a, b) in states {
+        for _ in 0..DEBOUNCE {
+            wheel.update(a, b);
+        }
+    }
+}
+
+fn backward() -> Vec<(bool, bool)> {
+    // 00 -> 01 -> 11 -> 10 -> 00
+    let mut states: Vec<_> = FORWARD[..3].iter().rev().copied().collect();
+    states.push((false, false));
+    states
+}
+
+#[test]
+fn full_cycle() {
+    let mut wheel = Quadrature::new(false, false, 4, DEBOUNCE);
+    turn(&mut wheel, &FORWARD);
+    turn(&mut wheel, &FORWARD);
+    assert_eq!(wheel.take_detents(), 2);
+    turn(&mut wheel, &backward());
+    assert_eq!(wheel.take_detents(), -1);
+    assert_eq!(wheel.take_detents(), 0);
+}
+
+#[test]
+fn partial_step_is_kept() {
+    let mut wheel = Quadrature::new(false, false, 4, DEBOUNCE);
+    turn(&mut wheel, &FORWARD[..2]);
+    assert_eq!(wheel.take_detents(), 0);
+    turn(&mut wheel, &FORWARD[2..]);
+    assert_eq!(wheel.take_detents(), 1);
+}
+
+#[test]
+fn half_cycle() {
+    let mut wheel = Quadrature::new(false, false, 2, DEBOUNCE);
+    turn(&mut wheel, &FORWARD);
+    assert_eq!(wheel.take_detents(), 2);
+}
+
+#[test]
+fn debounce() {
+    let mut wheel = Quadrature::new(false, false, 4, DEBOUNCE);
+    // bouncing contacts, each state shorter than the debounce
+    for _ in 0..10 {
+        wheel.update(true, false);
+        wheel.update(false, false);
+    }
+    turn(&mut wheel, &[(false, false)]);
+    assert_eq!(wheel.take_detents(), 0);
+
+    // bouncing on the way, then settling in each state
+    for &(a, b) in FORWARD.iter() {
+        wheel.update(!a, b);
+        wheel.update(a, b);
+        wheel.update(!a, b);
+        turn(&mut wheel, &[(a, b)]);
+    }
+    assert_eq!(wheel.take_detents(), 1);
+}
+
+#[test]
+fn skipped_state_is_ignored() {
+    // both lines changed, the direction is unknown
+    let mut wheel = Quadrature::new(false, false, 4, DEBOUNCE);
+    turn(&mut wheel, &[(true, true), (false, false)]);
+    assert_eq!(wheel.take_detents(), 0);
+}
+
+#[test]
+fn realigns_in_detent() {
+    // 11 is missed, 3 transitions counted for the detent
+    let mut wheel = Quadrature::new(false, false, 4, DEBOUNCE);
+    turn(&mut wheel, &[FORWARD[0], FORWARD[2], FORWARD[3]]);
+    assert_eq!(wheel.take_detents(), 1);
+    // the following detents aren't offset
+    turn(&mut wheel, &FORWARD[..3]);
+    assert_eq!(wheel.take_detents(), 0);
+    turn(&mut wheel, &FORWARD[3..]);
+    assert_eq!(wheel.take_detents(), 1);
+}
+
+#[test]
+fn counter_wraps() {
+    let mut wheel = Counter::new(0xfffe, 4);
+    wheel.update(0x0002);
+    assert_eq!(wheel.take_detents(), 1);
+    wheel.update(0x0001);
+    wheel.update(0xfffa);
+    assert_eq!(wheel.take_detents(), -2);
+}
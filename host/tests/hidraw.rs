@@ -0,0 +1,136 @@
+//! The feature reports of `Hidraw::command`, against the firmware codec
+
+use std::fs;
+use std::path::Path;
+
+use host::buttons::{Action, ButtonMap};
+use host::config_proto::{self, Command, LedMode, Response, Status, REPORT_ID};
+use host::hidraw::{self, Error, Hidraw};
+use host::usb_env;
+
+// A device answering as the firmware, `result` for the decoded command
+fn device<F>(request: &[u8], result: F) -> Vec<u8>
+where
+    F: FnOnce(Command) -> Result<Response, Status>,
+{
+    assert_eq!(request[0], REPORT_ID);
+    let result = Command::decode(&request[1..]).and_then(result);
+    let mut report = vec![REPORT_ID];
+    report.extend_from_slice(&config_proto::encode_response(request[2], &result));
+    report
+}
+
+#[test]
+fn request_and_response() {
+    let mut map = ButtonMap::default();
+    map.set(
+        4,
+        Action::Key {
+            modifiers: 0x01,
+            key: 0x06,
+        },
+    )
+    .unwrap();
+    let command = Command::SetButtonMap(map);
+    let report = hidraw::request(&command);
+    assert_eq!(report.len(), config_proto::REPORT_SIZE + 1);
+
+    let response = device(&report, |received| {
+        assert_eq!(received, command);
+        Ok(Response::Done)
+    });
+    assert!(matches!(
+        hidraw::response(&command, &response),
+        Ok(Response::Done)
+    ));
+
+    let command = Command::GetLed;
+    let response = device(&hidraw::request(&command), |_| {
+        Ok(Response::Led(LedMode::On))
+    });
+    assert!(matches!(
+        hidraw::response(&command, &response),
+        Ok(Response::Led(LedMode::On))
+    ));
+}
+
+#[test]
+fn pending_and_errors() {
+    let command = Command::Save;
+    let request = hidraw::request(&command);
+    for status in [Status::Pending, Status::Failed].iter() {
+        let response = device(&request, |_| Err(*status));
+        match hidraw::response(&command, &response) {
+            Err(Error::Status(s)) => assert_eq!(s, *status),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    // a request of another protocol version
+    let mut request = hidraw::request(&Command::GetCpi);
+    request[1] = config_proto::VERSION + 1;
+    let response = device(&request, |_| Ok(Response::Cpi(800)));
+    assert!(matches!(
+        hidraw::response(&Command::GetCpi, &response),
+        Err(Error::Status(Status::BadVersion))
+    ));
+}
+
+#[test]
+fn mismatch() {
+    let response = device(&hidraw::request(&Command::GetCpi), |_| {
+        Ok(Response::Cpi(800))
+    });
+    assert!(matches!(
+        hidraw::response(&Command::GetLed, &response),
+        Err(Error::Mismatch(id)) if id == Command::GetCpi.id()
+    ));
+}
+
+#[test]
+fn short_read() {
+    for n in 0..3 {
+        let report = [REPORT_ID, config_proto::VERSION, Command::GetCpi.id()];
+        assert!(matches!(
+            hidraw::response(&Command::GetCpi, &report[..n]),
+            Err(Error::Status(Status::BadVersion))
+        ));
+    }
+}
+
+#[test]
+fn find_skips_nodes_without_uevent() {
+    let class = std::env::temp_dir().join(format!("hidraw-{}", std::process::id()));
+    let node = |name: &str, uevent: Option<&str>| {
+        let device = class.join(name).join("device");
+        fs::create_dir_all(&device).unwrap();
+        if let Some(uevent) = uevent {
+            fs::write(device.join("uevent"), uevent).unwrap();
+        }
+    };
+    let mouse = format!(
+        "DRIVER=hid-generic\nHID_ID=0003:{:08X}:{:08X}\n",
+        usb_env::VID,
+        usb_env::PID
+    );
+    node("hidraw0", None);
+    node("hidraw1", Some(&mouse));
+    node("hidraw2", Some("HID_ID=0003:0000046D:0000C077\n"));
+    node("hidraw3", Some(&mouse));
+
+    let found = Hidraw::find_in(&class);
+    fs::remove_dir_all(&class).unwrap();
+    assert_eq!(
+        found.unwrap(),
+        [Path::new("/dev/hidraw1"), Path::new("/dev/hidraw3")]
+    );
+}
+
+#[test]
+fn usb_ids() {
+    assert_eq!(usb_env::parse_u16("0xc410"), Some(usb_env::VID));
+    assert_eq!(usb_env::parse_u16("16"), Some(16));
+    assert_eq!(usb_env::parse_u16("0x10000"), None);
+    assert_eq!(usb_env::parse_u16("0X10"), None);
+    assert_eq!(usb_env::parse_u16(""), None);
+}
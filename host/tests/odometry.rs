@@ -0,0 +1,104 @@
+//! Two-sensor odometry on synthetic motion traces
+
+use std::f32::consts::PI;
+
+use host::odometry::{Mount, Odometry};
+
+// 0.0254 mm per count
+const CPI: u16 = 1000;
+
+// Sensors 60 mm apart, left and right of the reference point
+fn odometry() -> Odometry {
+    let mount = |y| Mount {
+        x: 0.0,
+        y,
+        angle: 0.0,
+        cpi: CPI,
+    };
+    Odometry::new(mount(30.0), mount(-30.0))
+}
+
+fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} is not within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+#[test]
+fn straight_line() {
+    let mut odometry = odometry();
+    for _ in 0..10 {
+        odometry.update((1000, 0), (1000, 0));
+    }
+    let (x, y) = odometry.position();
+    assert_near(x, 254.0, 1e-3);
+    assert_near(y, 0.0, 1e-3);
+    assert_near(odometry.heading(), 0.0, 1e-6);
+}
+
+#[test]
+fn pure_rotation() {
+    // the left sensor moves back, the right forward, turning counter
+    // clockwise by 5.08 mm / 60 mm per step
+    let step = 5.08 / 60.0;
+    let mut odometry = odometry();
+    let mut heading = 0.0f32;
+    for _ in 0..200 {
+        odometry.update((-100, 0), (100, 0));
+        heading += step;
+        let (x, y) = odometry.position();
+        assert_near(x, 0.0, 1e-3);
+        assert_near(y, 0.0, 1e-3);
+        // wrapped into [-PI, PI)
+        let expected = (heading + PI).rem_euclid(2.0 * PI) - PI;
+        assert!(odometry.heading() >= -PI && odometry.heading() < PI);
+        assert_near(odometry.heading(), expected, 1e-3);
+    }
+}
+
+#[test]
+fn circle() {
+    // 2.54 mm forward, turning left by 0.508 mm / 60 mm per step, a
+    // radius of 300 mm around (0, 300)
+    let (radius, step) = (300.0, 0.508 / 60.0);
+    let mut odometry = odometry();
+    let steps = (2.0 * PI / step).round() as usize;
+    for _ in 0..steps {
+        odometry.update((90, 0), (110, 0));
+        let (x, y) = odometry.position();
+        assert_near((x * x + (y - radius) * (y - radius)).sqrt(), radius, 0.1);
+    }
+    // back at the start, after a full turn
+    let (x, y) = odometry.position();
+    assert_near(x, 0.0, 2.0);
+    assert_near(y, 0.0, 2.0);
+    assert_near(odometry.heading(), 0.0, 0.01);
+}
+
+#[test]
+fn pose_wraps() {
+    let mut odometry = odometry();
+    odometry.set_pose(0.0, 0.0, 3.0 * PI);
+    assert_near(odometry.heading(), -PI, 1e-5);
+    // far beyond the f32 resolution of 2 PI, no endless wrapping
+    odometry.set_pose(0.0, 0.0, 1e30);
+    assert!(odometry.heading() >= -PI && odometry.heading() < PI);
+    odometry.set_pose(0.0, 0.0, f32::INFINITY);
+    assert!(odometry.heading().is_nan());
+}
+
+#[test]
+#[should_panic]
+fn zero_resolution() {
+    let mount = |y, cpi| Mount {
+        x: 0.0,
+        y,
+        angle: 0.0,
+        cpi,
+    };
+    Odometry::new(mount(30.0, CPI), mount(-30.0, 0));
+}
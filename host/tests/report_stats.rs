@@ -0,0 +1,54 @@
+//! Report rate, missed and empty polling intervals
+
+use host::report_stats::ReportStats;
+
+#[test]
+fn full_rate() {
+    let mut stats = ReportStats::new(1);
+    for _ in 0..1000 {
+        stats.sent();
+        stats.tick(1, false);
+    }
+    assert_eq!(stats.rate(), 1000);
+    assert_eq!((stats.missed(), stats.empty()), (0, 0));
+}
+
+#[test]
+fn missed_and_empty() {
+    // 4 ms frames
+    let mut stats = ReportStats::new(4);
+    // motion waiting, nothing sent
+    stats.tick(4, true);
+    // nothing to report
+    stats.tick(8, false);
+    // sent late in the frame
+    stats.tick(3, true);
+    stats.sent();
+    stats.tick(1, false);
+    assert_eq!(stats.missed(), 1);
+    assert_eq!(stats.empty(), 2);
+}
+
+#[test]
+fn a_report_counts_for_its_frame_only() {
+    let mut stats = ReportStats::new(2);
+    stats.sent();
+    stats.tick(4, true);
+    assert_eq!(stats.missed(), 1);
+}
+
+#[test]
+fn rate_over_the_last_second() {
+    let mut stats = ReportStats::new(8);
+    for _ in 0..125 {
+        stats.sent();
+        stats.tick(8, false);
+    }
+    assert_eq!(stats.rate(), 125);
+    // not updated before the next window is complete
+    stats.tick(999, false);
+    assert_eq!(stats.rate(), 125);
+    stats.tick(1, false);
+    assert_eq!(stats.rate(), 0);
+    assert_eq!(stats.missed(), 0);
+}
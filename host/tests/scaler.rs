@@ -0,0 +1,51 @@
+//! Fixed-point motion scaling
+
+use host::scaler::{MotionScaler, ONE};
+
+#[test]
+fn ratio() {
+    assert_eq!(MotionScaler::from_ratio(1, 1).factor(), ONE);
+    assert_eq!(MotionScaler::from_ratio(3, 2).factor(), ONE + ONE / 2);
+    assert_eq!(MotionScaler::from_ratio(1, 3).factor(), ONE / 3);
+    // beyond 16 bits of numerator
+    assert_eq!(MotionScaler::from_ratio(70_000, 2).factor(), 35_000 * ONE);
+    // saturated, and no division by zero
+    assert_eq!(MotionScaler::from_ratio(100_000, 1).factor(), u32::MAX);
+    assert_eq!(MotionScaler::from_ratio(1, 0).factor(), ONE);
+}
+
+#[test]
+fn rounds_towards_negative_infinity() {
+    let mut scaler = MotionScaler::from_ratio(1, 2);
+    assert_eq!(scaler.scale(1, -1), (0, -1));
+    // the halves carried over
+    assert_eq!(scaler.scale(1, -1), (1, 0));
+    assert_eq!(scaler.scale(0, 0), (0, 0));
+}
+
+#[test]
+fn remainder_carry() {
+    // 0.3, the outputs sum to the floored scaled sum of the inputs
+    let mut scaler = MotionScaler::new(ONE * 3 / 10);
+    let (mut sum_x, mut sum_y) = (0, 0);
+    let (mut in_x, mut in_y) = (0i64, 0i64);
+    for i in 0..1000 {
+        let (dx, dy) = ((i % 7) as i16, -((i % 5) as i16));
+        in_x += dx as i64;
+        in_y += dy as i64;
+        let (x, y) = scaler.scale(dx, dy);
+        sum_x += x as i64;
+        sum_y += y as i64;
+        assert_eq!(sum_x, (in_x * (ONE as i64 * 3 / 10)) >> 16);
+        assert_eq!(sum_y, (in_y * (ONE as i64 * 3 / 10)) >> 16);
+    }
+}
+
+#[test]
+fn reset_drops_fractions() {
+    let mut scaler = MotionScaler::from_ratio(1, 2);
+    assert_eq!(scaler.scale(1, 1), (0, 0));
+    scaler.reset();
+    assert_eq!(scaler.scale(1, 1), (0, 0));
+    assert_eq!(scaler.scale(1, 1), (1, 1));
+}
@@ -0,0 +1,72 @@
+//! Wheel and pan in report units, with and without high-resolution
+//! scrolling
+
+use host::scroll::{Scroll, HIRES_MULTIPLIER};
+
+const LOW: (u8, u8) = (1, 1);
+const HIGH: (u8, u8) = (HIRES_MULTIPLIER, HIRES_MULTIPLIER);
+const HIRES: i32 = HIRES_MULTIPLIER as i32;
+
+#[test]
+fn detents() {
+    let mut scroll = Scroll::new(40);
+    scroll.add_detents(2, -1);
+    assert_eq!(scroll.take(LOW), (2, -1));
+    assert_eq!(scroll.take(LOW), (0, 0));
+
+    scroll.add_detents(1, 1);
+    assert_eq!(scroll.take(HIGH), (HIRES, HIRES));
+}
+
+#[test]
+fn remainder() {
+    // 1.5 detents up, 1.5 down, whole detents only without high resolution
+    let mut scroll = Scroll::new(40);
+    scroll.add_hires(HIRES + HIRES / 2, -HIRES - HIRES / 2);
+    assert_eq!(scroll.take(LOW), (1, -1));
+    // the half detent is kept, and completed
+    scroll.add_hires(HIRES / 2, -HIRES / 2);
+    assert_eq!(scroll.take(LOW), (1, -1));
+    assert_eq!(scroll.take(LOW), (0, 0));
+
+    // the remainder is taken in high-resolution units
+    scroll.add_hires(3, 0);
+    assert_eq!(scroll.take(LOW), (0, 0));
+    assert_eq!(scroll.take(HIGH), (3, 0));
+}
+
+#[test]
+fn mixed_multipliers() {
+    // high-resolution wheel, low-resolution pan
+    let mut scroll = Scroll::new(40);
+    scroll.add_hires(3, 3);
+    assert_eq!(scroll.take((HIRES_MULTIPLIER, 1)), (3, 0));
+    scroll.add_hires(0, HIRES - 3);
+    assert_eq!(scroll.take((HIRES_MULTIPLIER, 1)), (0, 1));
+}
+
+#[test]
+fn motion() {
+    // moving forward (y negative) scrolls up, right pans right
+    let mut scroll = Scroll::new(40);
+    for _ in 0..10 {
+        scroll.add_motion(4, -4);
+    }
+    assert_eq!(scroll.take(LOW), (1, 1));
+
+    // sub-detent motion is carried over samples
+    for _ in 0..8 {
+        scroll.add_motion(0, 1);
+    }
+    assert_eq!(scroll.take(HIGH), (-HIRES * 8 / 40, 0));
+}
+
+#[test]
+fn reset_motion() {
+    let mut scroll = Scroll::new(40);
+    scroll.add_motion(0, -4);
+    scroll.reset_motion();
+    scroll.add_motion(0, -4);
+    // 2 x 4 counts would be a high-resolution unit
+    assert_eq!(scroll.take(HIGH), (0, 0));
+}
@@ -0,0 +1,296 @@
+//! The emulated SPI path against the simulator
+//!
+//! The `Pmw3389e` driver talks to a simulated PMW3389 on SS0 of a
+//! simulated SC18IS602, through the bridge driver (NCS managed as GPIO),
+//! with both completion strategies (polling and INT). Transfers beyond the
+//! data buffer, spare lines as GPIOs and devices sharing the bridge are
+//! run against a recording device.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal::spi::MODE_3;
+
+use host::pmw3389e::Pmw3389e;
+use host::register::Register;
+use host::sc18is602::{
+    Completion, Error, GpioMode, Interrupt, Order, Polling, Speed, SsMode, BUFFER_SIZE, SH18IS602,
+    SS_SETUP_US,
+};
+use host::sim::{self, Delay, Pmw3389, Sc18is602, SpiSlave};
+
+// A device recording what it receives while selected, answering the
+// complement
+#[derive(Default)]
+struct Recorder {
+    selects: u32,
+    received: Vec<u8>,
+}
+
+impl SpiSlave for Recorder {
+    fn select(&mut self, selected: bool) {
+        if selected {
+            self.selects += 1;
+        }
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        self.received.push(mosi);
+        !mosi
+    }
+}
+
+type Bridge =
+    SH18IS602<Sc18is602, Polling<Delay>, RefCell<host::sc18is602::Bus<Sc18is602, Polling<Delay>>>>;
+
+// A bridge with polled completion
+fn bridge(model: &Sc18is602, delay: &Delay) -> Bridge {
+    SH18IS602::with_mutex(
+        model.clone(),
+        Polling::new(delay.clone()),
+        0,
+        Order::MsbFirst,
+        MODE_3,
+        Speed::Speed1843kHz,
+    )
+    .unwrap()
+}
+
+// Bytes 0, 1, 2.. (wrapping)
+fn words(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+fn run<C: Completion>(model: Sc18is602, completion: C, delay: Delay) {
+    let sensor = Rc::new(RefCell::new(Pmw3389::new()));
+    model.attach(0, sensor.clone());
+
+    let mut bridge = SH18IS602::<_, _, RefCell<_>>::with_mutex(
+        model.clone(),
+        completion,
+        0,
+        Order::MsbFirst,
+        MODE_3,
+        Speed::Speed1843kHz,
+    )
+    .unwrap();
+    let parts = bridge.split();
+    let spi = parts.ss0.into_spi(SsMode::Gpio).unwrap();
+
+    let mut pmw3389 = Pmw3389e::new(spi, delay.clone()).unwrap();
+
+    assert_eq!(pmw3389.product_id().unwrap(), 0x47);
+    // firmware downloaded
+    assert_eq!(
+        pmw3389.read_register(Register::SROMId).unwrap(),
+        sim::SROM_ID
+    );
+
+    sensor.borrow_mut().move_by(300, -20);
+    let motion = pmw3389.read_register(Register::Motion).unwrap();
+    let mut delta = [0; 4];
+    for (d, reg) in delta.iter_mut().zip(&[
+        Register::DeltaXL,
+        Register::DeltaXH,
+        Register::DeltaYL,
+        Register::DeltaYH,
+    ]) {
+        *d = pmw3389.read_register(*reg).unwrap();
+    }
+    assert_ne!(motion & 0x80, 0);
+    assert_eq!(i16::from_le_bytes([delta[0], delta[1]]), 300);
+    assert_eq!(i16::from_le_bytes([delta[2], delta[3]]), -20);
+
+    // NCS released
+    assert_ne!(model.gpio().2 & 1, 0);
+    assert!(delay.elapsed_us() > 0);
+}
+
+#[test]
+fn polling() {
+    let delay = Delay::new();
+    run(Sc18is602::new(0), Polling::new(delay.clone()), delay);
+}
+
+#[test]
+fn polling_retries_while_busy() {
+    let delay = Delay::new();
+    let model = Sc18is602::new(0);
+    model.set_busy(5);
+    run(model.clone(), Polling::new(delay.clone()), delay);
+    assert!(model.nacks() > 0);
+}
+
+// Times out a transfer of `len` bytes, returning the probes and the time waited
+fn time_out(speed: Speed, len: usize) -> (u32, u64) {
+    let delay = Delay::new();
+    let model = Sc18is602::new(0);
+    let mut bridge = SH18IS602::<_, _, RefCell<_>>::with_mutex(
+        model.clone(),
+        Polling::new(delay.clone()),
+        0,
+        Order::MsbFirst,
+        MODE_3,
+        speed,
+    )
+    .unwrap();
+    let parts = bridge.split();
+    let mut spi = parts.ss0.into_spi(SsMode::Hardware).unwrap();
+
+    model.set_busy(u32::MAX);
+    let mut words = vec![0; len];
+    assert!(matches!(spi.transfer(&mut words), Err(Error::Timeout)));
+    (model.nacks(), delay.elapsed_us())
+}
+
+#[test]
+fn polling_times_out() {
+    // 4 bytes at 1843 kHz take 18 us, 200 bytes at 58 kHz 27587 us
+    for &(speed, len, us) in &[
+        (Speed::Speed1843kHz, 4, 18),
+        (Speed::Speed58kHz, 200, 27587),
+    ] {
+        let (probes, waited) = time_out(speed, len);
+        // a bounded number of probes, over (at least) the timeout
+        assert_eq!(probes, 16);
+        assert!(waited >= 3 * us + 100, "{:?} waited {} us", speed, waited);
+        assert!(waited <= 4 * us + 200, "{:?} waited {} us", speed, waited);
+    }
+}
+
+#[test]
+fn interrupt() {
+    let delay = Delay::new();
+    let model = Sc18is602::new(0);
+    let int = Interrupt::new(model.int_pin(), delay.clone());
+    run(model, int, delay);
+}
+
+#[test]
+fn interrupt_while_busy() {
+    let delay = Delay::new();
+    let model = Sc18is602::new(0);
+    model.set_busy(5);
+    let int = Interrupt::new(model.int_pin(), delay.clone());
+    run(model.clone(), int, delay);
+    // INT is awaited, the bridge is not accessed while busy
+    assert_eq!(model.nacks(), 0);
+}
+
+#[test]
+fn hardware_ss_oversize() {
+    let (model, delay) = (Sc18is602::new(0), Delay::new());
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    model.attach(1, recorder.clone());
+    let mut bridge = bridge(&model, &delay);
+    let mut spi = bridge.split().ss1.into_spi(SsMode::Hardware).unwrap();
+
+    // the bridge would release SS between chunks, rejected
+    let mut buf = words(BUFFER_SIZE + 1);
+    assert!(matches!(spi.transfer(&mut buf), Err(Error::Oversize)));
+    assert!(matches!(spi.write(&buf), Err(Error::Oversize)));
+    assert!(recorder.borrow().received.is_empty());
+
+    // a full buffer in one transfer
+    let mut buf = words(BUFFER_SIZE);
+    spi.transfer(&mut buf).unwrap();
+    assert_eq!(
+        buf,
+        words(BUFFER_SIZE).iter().map(|b| !b).collect::<Vec<_>>()
+    );
+    assert_eq!(recorder.borrow().received, words(BUFFER_SIZE));
+    assert_eq!(recorder.borrow().selects, 1);
+}
+
+#[test]
+fn gpio_ss_split_transfer() {
+    let (model, delay) = (Sc18is602::new(0), Delay::new());
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    model.attach(0, recorder.clone());
+    let mut bridge = bridge(&model, &delay);
+    let mut spi = bridge.split().ss0.into_spi(SsMode::Gpio).unwrap();
+
+    // in chunks of the buffer, selected throughout
+    let len = 2 * BUFFER_SIZE + 50;
+    spi.set_low().unwrap();
+    let t = delay.elapsed_us();
+    let mut buf = words(len);
+    spi.transfer(&mut buf).unwrap();
+    spi.write(&words(len)).unwrap();
+    spi.set_high().unwrap();
+    assert!(t >= SS_SETUP_US as u64);
+
+    let recorder = recorder.borrow();
+    assert_eq!(recorder.selects, 1);
+    assert_eq!(recorder.received.len(), 2 * len);
+    assert_eq!(&recorder.received[..len], &words(len)[..]);
+    assert_eq!(buf, words(len).iter().map(|b| !b).collect::<Vec<_>>());
+}
+
+#[test]
+fn gpio_pins() {
+    let (model, delay) = (Sc18is602::new(0), Delay::new());
+    let mut bridge = bridge(&model, &delay);
+    let parts = bridge.split();
+    let mut led = parts.ss2.into_gpio(GpioMode::PushPull).unwrap();
+    let mut input = parts.ss3.into_gpio(GpioMode::InputOnly).unwrap();
+
+    led.set_low().unwrap();
+    assert_eq!(model.gpio(), (0b1100, 0b1001 << 4, 0b0000));
+    assert!(led.is_low().unwrap());
+    led.set_high().unwrap();
+    assert!(led.is_high().unwrap());
+
+    // an input floats (pulled up), and can't be driven
+    assert!(input.is_high().unwrap());
+    assert!(matches!(input.set_low(), Err(Error::NotConfigured)));
+    input.set_mode(GpioMode::OpenDrain).unwrap();
+    input.set_low().unwrap();
+    assert!(input.is_low().unwrap());
+    assert_eq!(input.mode(), GpioMode::OpenDrain);
+}
+
+#[test]
+fn gpio_ss_needs_a_spare_line() {
+    let (model, delay) = (Sc18is602::new(0), Delay::new());
+    let mut bridge = bridge(&model, &delay);
+    let parts = bridge.split();
+    let mut spi = parts.ss0.into_spi(SsMode::Gpio).unwrap();
+    let _ss1 = parts.ss1.into_spi(SsMode::Hardware).unwrap();
+    let _ss2 = parts.ss2.into_gpio(GpioMode::PushPull).unwrap();
+    let _ss3 = parts.ss3.into_gpio(GpioMode::PushPull).unwrap();
+
+    assert!(matches!(spi.write(&[0]), Err(Error::NoSpareSs)));
+}
+
+#[test]
+fn devices_share_the_bridge() {
+    let (model, delay) = (Sc18is602::new(0), Delay::new());
+    let sensor = Rc::new(RefCell::new(Pmw3389::new()));
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    model.attach(0, sensor.clone());
+    model.attach(1, recorder.clone());
+    let mut bridge = bridge(&model, &delay);
+    let parts = bridge.split();
+    let spi = parts.ss0.into_spi(SsMode::Gpio).unwrap();
+    let mut other = parts.ss1.into_spi(SsMode::Hardware).unwrap();
+
+    let mut pmw3389 = Pmw3389e::new(spi, delay.clone()).unwrap();
+    let received = recorder.borrow().received.len();
+    assert_eq!(received, 0);
+
+    // interleaved, each device sees its own traffic only
+    for i in 0..3 {
+        sensor.borrow_mut().move_by(10, 0);
+        let motion = pmw3389.read_register(Register::Motion).unwrap();
+        assert_ne!(motion & 0x80, 0);
+        let mut buf = [i, 0xa5];
+        other.transfer(&mut buf).unwrap();
+        assert_eq!(buf, [!i, 0x5a]);
+    }
+    assert_eq!(pmw3389.product_id().unwrap(), 0x47);
+    assert_eq!(recorder.borrow().received, [0, 0xa5, 1, 0xa5, 2, 0xa5]);
+    assert_eq!(recorder.borrow().selects, 3);
+}
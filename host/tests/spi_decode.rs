@@ -0,0 +1,138 @@
+//! Decoding of a small synthetic capture, in the format of a sigrok export
+
+use host::capture::{self, Channels};
+use host::decode::{self, Access};
+use host::register::Register;
+
+// Builds a SPI mode 3 capture, a row per signal change, time in us
+struct Trace {
+    t: f64,
+    sck: bool,
+    mosi: bool,
+    miso: bool,
+    ncs: bool,
+    csv: String,
+}
+
+impl Trace {
+    fn new() -> Self {
+        let mut trace = Trace {
+            t: 0.0,
+            sck: true,
+            mosi: false,
+            miso: false,
+            ncs: true,
+            csv: "; Samplerate: 24 MHz\nTime [us],SCK,MOSI,MISO,NCS\n".into(),
+        };
+        trace.row();
+        trace
+    }
+
+    fn row(&mut self) {
+        self.csv += &format!(
+            "{:.3},{},{},{},{}\n",
+            self.t, self.sck as u8, self.mosi as u8, self.miso as u8, self.ncs as u8
+        );
+    }
+
+    fn wait(&mut self, us: f64) {
+        self.t += us;
+    }
+
+    fn ncs(&mut self, high: bool) {
+        self.ncs = high;
+        self.row();
+        self.wait(1.0);
+    }
+
+    // MSB first, launched on the falling and sampled on the rising edge
+    fn byte(&mut self, mosi: u8, miso: u8) {
+        for bit in (0..8).rev() {
+            self.sck = false;
+            self.mosi = mosi >> bit & 1 != 0;
+            self.miso = miso >> bit & 1 != 0;
+            self.row();
+            self.wait(0.5);
+            self.sck = true;
+            self.row();
+            self.wait(0.5);
+        }
+    }
+
+    // Reads `addr`, the sensor answering `data`, after `srad` us
+    fn read(&mut self, addr: u8, data: u8, srad: f64) {
+        self.ncs(false);
+        self.byte(addr, 0);
+        self.wait(srad);
+        self.byte(0, data);
+        self.ncs(true);
+        self.wait(200.0);
+    }
+
+    fn write(&mut self, addr: u8, data: u8) {
+        self.ncs(false);
+        self.byte(addr | 0x80, 0);
+        self.byte(data, 0);
+        self.wait(40.0);
+        self.ncs(true);
+        self.wait(200.0);
+    }
+
+    fn decode(&self) -> Vec<decode::Transaction> {
+        let samples = capture::parse(self.csv.as_bytes(), &Channels::default(), None).unwrap();
+        decode::transactions(&samples, 3)
+    }
+}
+
+#[test]
+fn reads_and_writes() {
+    let mut trace = Trace::new();
+    trace.read(Register::ProductId.addr(), 0x47, 200.0);
+    trace.write(Register::Config2.addr(), 0x20);
+    trace.read(Register::Config2.addr(), 0x20, 200.0);
+
+    let transactions = trace.decode();
+    let accesses: Vec<Access> = transactions.iter().map(|tr| tr.access()).collect();
+    assert_eq!(
+        accesses,
+        vec![
+            Access::Read {
+                addr: 0x00,
+                data: vec![0x47]
+            },
+            Access::Write {
+                addr: 0x10,
+                data: vec![0x20]
+            },
+            Access::Read {
+                addr: 0x10,
+                data: vec![0x20]
+            },
+        ]
+    );
+    assert!(transactions.iter().all(|tr| tr.partial_bits == 0));
+    assert_eq!(decode::check(&transactions), vec![]);
+}
+
+#[test]
+fn reset_without_clocks() {
+    let mut trace = Trace::new();
+    trace.ncs(false);
+    trace.ncs(true);
+
+    let transactions = trace.decode();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].access(), Access::Reset);
+}
+
+#[test]
+fn short_address_to_data_delay() {
+    let mut trace = Trace::new();
+    trace.read(Register::ProductId.addr(), 0x47, 100.0);
+
+    let violations = decode::check(&trace.decode());
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].index, 0);
+    assert_eq!(violations[0].what, "tSRAD");
+    assert!(violations[0].actual < decode::T_SRAD);
+}
@@ -0,0 +1,83 @@
+//! USB suspend, resume and remote wakeup
+
+use host::suspend::{Event, Suspend, HOLDOFF_MS, RESUME_MS};
+
+// Sample period while suspended
+const MS: u32 = 5;
+
+#[test]
+fn suspend_and_resume() {
+    let mut suspend = Suspend::new();
+    assert_eq!(suspend.update(false, false, true, 1), None);
+    assert_eq!(
+        suspend.update(true, false, false, 1),
+        Some(Event::Suspend { shutdown: true })
+    );
+    assert!(suspend.is_suspended() && suspend.is_shutdown());
+    assert!(suspend.may_stop());
+    // no remote wakeup, activity is ignored
+    for _ in 0..100 {
+        assert_eq!(suspend.update(true, false, true, MS), None);
+    }
+    assert_eq!(
+        suspend.update(false, false, false, MS),
+        Some(Event::Resume { power_up: true })
+    );
+    assert!(!suspend.is_suspended() && !suspend.is_shutdown());
+    assert!(!suspend.may_stop());
+}
+
+#[test]
+fn remote_wakeup() {
+    let mut suspend = Suspend::new();
+    assert_eq!(
+        suspend.update(true, true, false, 1),
+        Some(Event::Suspend { shutdown: false })
+    );
+    // activity before the bus has been idle for the holdoff
+    let mut t = 0;
+    while t + MS < HOLDOFF_MS {
+        assert_eq!(suspend.update(true, true, true, MS), None);
+        t += MS;
+    }
+    assert_eq!(
+        suspend.update(true, true, true, MS),
+        Some(Event::SignalResume)
+    );
+    // held for RESUME_MS, even if the host resumes meanwhile, the USB
+    // clocked (not stopped) meanwhile
+    let mut t = 0;
+    while t + MS < RESUME_MS {
+        assert!(!suspend.may_stop());
+        assert_eq!(suspend.update(false, true, true, MS), None);
+        t += MS;
+    }
+    assert_eq!(
+        suspend.update(false, true, true, MS),
+        Some(Event::EndResume)
+    );
+    assert_eq!(
+        suspend.update(false, true, false, MS),
+        Some(Event::Resume { power_up: false })
+    );
+}
+
+#[test]
+fn signalling_repeats_after_holdoff() {
+    let mut suspend = Suspend::new();
+    suspend.update(true, true, false, 1);
+    assert_eq!(
+        suspend.update(true, true, true, HOLDOFF_MS),
+        Some(Event::SignalResume)
+    );
+    assert_eq!(
+        suspend.update(true, true, true, RESUME_MS),
+        Some(Event::EndResume)
+    );
+    // the host didn't resume, idle again before the next attempt
+    assert_eq!(suspend.update(true, true, true, HOLDOFF_MS - 1), None);
+    assert_eq!(
+        suspend.update(true, true, true, 1),
+        Some(Event::SignalResume)
+    );
+}
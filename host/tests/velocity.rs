@@ -0,0 +1,94 @@
+//! Speed, acceleration and malfunction detection
+
+use host::velocity::{Malfunction, Velocity, SPIKE_ACCEL};
+
+// 1 MHz cycle counter, cycles in us
+const FREQ: u32 = 1_000_000;
+const CPI: u16 = 1000;
+// 8 ms polling
+const POLL: u32 = 8_000;
+
+// Counts per report at `ips`
+fn counts(ips: f32) -> i16 {
+    (ips * CPI as f32 * POLL as f32 / FREQ as f32) as i16
+}
+
+// A velocity settled at `ips`, with the time of the last report
+fn moving(ips: f32) -> (Velocity, u32) {
+    let mut velocity = Velocity::new(FREQ, CPI);
+    let mut t = 0;
+    for _ in 0..3 {
+        assert_eq!(velocity.update(t, counts(ips), 0), None);
+        t += POLL;
+    }
+    (velocity, t - POLL)
+}
+
+#[test]
+fn speed_and_acceleration() {
+    let (mut velocity, t) = moving(100.0);
+    assert!((velocity.speed() - 100.0).abs() < 0.1);
+    assert!(velocity.accel().abs() < 0.1);
+
+    // +20 IPS in 8 ms, 6.5 g
+    assert_eq!(velocity.update(t + POLL, counts(120.0), 0), None);
+    assert!((velocity.accel() - 20.0 / 0.008 / 386.0886).abs() < 0.1);
+    assert!((velocity.peak_speed() - 120.0).abs() < 0.1);
+    assert_eq!(velocity.malfunctions(), 0);
+}
+
+#[test]
+fn peaks_beyond_the_spec() {
+    // 100 to 300 IPS in 8 ms, about 65 g, beyond the 50 g of the spec
+    let (mut velocity, t) = moving(100.0);
+    assert_eq!(velocity.update(t + POLL, counts(300.0), 0), None);
+    assert!(velocity.peak_accel() > 60.0);
+    assert!((velocity.peak_speed() - 300.0).abs() < 0.1);
+    assert_eq!(velocity.malfunctions(), 0);
+}
+
+#[test]
+fn zero_output_below_the_acceleration_limit() {
+    // 150 IPS to 0 in 8 ms is about 48.6 g, below the spike limit
+    let (mut velocity, t) = moving(150.0);
+    match velocity.update(t + POLL, 0, 0) {
+        Some(Malfunction::ZeroOutput { speed }) => assert!((speed - 150.0).abs() < 0.1),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(velocity.malfunctions(), 1);
+    // flagged once, still stopped
+    assert_eq!(velocity.update(t + 2 * POLL, 0, 0), None);
+}
+
+#[test]
+fn slow_stop_is_no_zero_output() {
+    let (mut velocity, t) = moving(20.0);
+    assert_eq!(velocity.update(t + POLL, 0, 0), None);
+    assert_eq!(velocity.malfunctions(), 0);
+}
+
+#[test]
+fn spike() {
+    // 100 to 1000 IPS in 8 ms, about 290 g
+    let (mut velocity, t) = moving(100.0);
+    match velocity.update(t + POLL, counts(1000.0), 0) {
+        Some(Malfunction::Spike { accel }) => assert!(accel > SPIKE_ACCEL),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(velocity.malfunctions(), 1);
+    // kept out of the peaks, and no reference for the next report
+    assert!(velocity.peak_speed() < 101.0);
+    assert_eq!(velocity.update(t + 2 * POLL, counts(100.0), 0), None);
+}
+
+#[test]
+fn configured_spike_limit() {
+    // 65 g, a spike below a limit set at the spec
+    let (mut velocity, t) = moving(100.0);
+    velocity.set_limits(50.0, 40.0);
+    assert!(matches!(
+        velocity.update(t + POLL, counts(300.0), 0),
+        Some(Malfunction::Spike { .. })
+    ));
+    assert_eq!(velocity.peak_accel(), 0.0);
+}
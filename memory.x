@@ -3,10 +3,16 @@ MEMORY
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   /* TODO Adjust these memory regions to match your device memory layout */
   /* These values correspond to the STM32F411 */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 128K
+  /* Sectors 0-6 of the 512K flash, sector 7 (0x08060000, 128K) stores the
+     configuration of Project_Mouse (src/flash.rs) */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 384K
   RAM : ORIGIN = 0x20000000, LENGTH = 64K
 }
 
+/* The program may not grow into the configuration sector */
+ASSERT(ORIGIN(FLASH) + LENGTH(FLASH) <= 0x08060000,
+       "memory.x: FLASH overlaps the configuration sector of src/flash.rs");
+
 /* This is where the call stack will be allocated. */
 /* The stack is of the full descending type. */
 /* You may want to use this variable to locate the call stack and static
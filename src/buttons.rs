@@ -0,0 +1,125 @@
+//! Button mapping
+//!
+//! Decides per physical button whether it acts as a mouse button or as a
+//! keyboard usage (a key with modifiers), e.g., copy/paste or push-to-talk
+//! on the side buttons, or turns sensor motion into scrolling while held.
+//!
+//! Target independent, so it can be exercised on the host.
+
+use crate::keyboard::{KeyboardReport, KEY_MAX};
+
+/// Physical buttons, in order left, right, wheel, M2 (PA5), M1 (PA4)
+pub const BUTTONS: usize = 5;
+
+/// Buttons of the mouse report, `Action::Mouse` bits 0 to 4
+pub const MOUSE_BUTTONS: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Not reported
+    None,
+    /// Mouse button, bit 0 left, 1 right, 2 middle, 3 back, 4 forward
+    Mouse(u8),
+    /// Keyboard usage ID with modifier bits, e.g., `keyboard::MOD_LCTRL`
+    Key { modifiers: u8, key: u8 },
+    /// While held, sensor motion scrolls instead of moving the pointer
+    Scroll,
+}
+
+impl Action {
+    /// Fits the reports, a mouse button below `MOUSE_BUTTONS` or a key up
+    /// to `KEY_MAX`
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Action::Mouse(bit) => bit < MOUSE_BUTTONS,
+            Action::Key { key, .. } => key <= KEY_MAX,
+            Action::None | Action::Scroll => true,
+        }
+    }
+}
+
+/// An action beyond the reports, rejected by `ButtonMap::set`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidAction(pub Action);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ButtonMap {
+    actions: [Action; BUTTONS],
+}
+
+impl ButtonMap {
+    pub fn action(&self, button: usize) -> Action {
+        self.actions[button]
+    }
+
+    /// Maps `button` to `action`, unless it doesn't fit the reports
+    pub fn set(&mut self, button: usize, action: Action) -> Result<(), InvalidAction> {
+        if !action.is_valid() {
+            return Err(InvalidAction(action));
+        }
+        self.actions[button] = action;
+        Ok(())
+    }
+
+    /// Maps the pressed buttons (bit n for button n) to the mouse buttons
+    /// and the keyboard state
+    pub fn map(&self, pressed: u8) -> (u8, KeyboardReport) {
+        let mut buttons = 0;
+        let mut keys = KeyboardReport::default();
+        for (n, action) in self.actions.iter().enumerate() {
+            if pressed & 1 << n == 0 {
+                continue;
+            }
+            match *action {
+                Action::None | Action::Scroll => {}
+                Action::Mouse(bit) => buttons |= 1 << bit,
+                Action::Key { modifiers, key } => {
+                    keys.modifiers |= modifiers;
+                    keys.press(key);
+                }
+            }
+        }
+        (buttons, keys)
+    }
+
+    /// Some button is mapped to a key
+    pub fn has_keys(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|action| matches!(action, Action::Key { .. }))
+    }
+
+    /// Maps the buttons mapped to keys back to their default, e.g., when
+    /// there is no keyboard interface
+    pub fn clear_keys(&mut self) {
+        let default = ButtonMap::default();
+        for (n, action) in self.actions.iter_mut().enumerate() {
+            if let Action::Key { .. } = action {
+                *action = default.actions[n];
+            }
+        }
+    }
+
+    /// A button mapped to `Action::Scroll` is pressed
+    pub fn scrolling(&self, pressed: u8) -> bool {
+        self.actions
+            .iter()
+            .enumerate()
+            .any(|(n, action)| *action == Action::Scroll && pressed & 1 << n != 0)
+    }
+}
+
+/// Each button as the mouse button of the same number
+impl Default for ButtonMap {
+    fn default() -> Self {
+        ButtonMap {
+            actions: [
+                Action::Mouse(0),
+                Action::Mouse(1),
+                Action::Mouse(2),
+                Action::Mouse(3),
+                Action::Mouse(4),
+            ],
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! USB CDC-ACM serial class
+//!
+//! A virtual serial port (/dev/ttyACMn, COMn) in the composite device,
+//! a communication interface with the notification endpoint and a data
+//! interface with the bulk endpoints, grouped by an interface association
+//! (the device is to be built with `composite_with_iads`).
+//!
+//! The line coding is kept but has no effect, data is passed as is. Used
+//! by the console of Project_Mouse (feature `console`), see `console`.
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+const USB_CLASS_CDC: u8 = 0x02;
+const USB_CLASS_CDC_DATA: u8 = 0x0a;
+const CDC_SUBCLASS_ACM: u8 = 0x02;
+const CDC_PROTOCOL_NONE: u8 = 0x00;
+
+const CS_INTERFACE: u8 = 0x24;
+const CDC_TYPE_HEADER: u8 = 0x00;
+const CDC_TYPE_CALL_MANAGEMENT: u8 = 0x01;
+const CDC_TYPE_ACM: u8 = 0x02;
+const CDC_TYPE_UNION: u8 = 0x06;
+
+const REQ_SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+const REQ_SET_LINE_CODING: u8 = 0x20;
+const REQ_GET_LINE_CODING: u8 = 0x21;
+const REQ_SET_CONTROL_LINE_STATE: u8 = 0x22;
+const REQ_SEND_BREAK: u8 = 0x23;
+
+/// Bulk packet size
+pub const MAX_PACKET: usize = 64;
+
+pub struct SerialClass<'a, B: UsbBus> {
+    comm_if: InterfaceNumber,
+    comm_ep: EndpointIn<'a, B>,
+    data_if: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    // dwDTERate, bCharFormat, bParityType, bDataBits
+    line_coding: [u8; 7],
+    dtr: bool,
+}
+
+impl<B: UsbBus> SerialClass<'_, B> {
+    /// Creates a new serial port, 3 endpoints
+    pub fn new(alloc: &UsbBusAllocator<B>) -> SerialClass<'_, B> {
+        SerialClass {
+            comm_if: alloc.interface(),
+            comm_ep: alloc.interrupt(8, 255),
+            data_if: alloc.interface(),
+            read_ep: alloc.bulk(MAX_PACKET as u16),
+            write_ep: alloc.bulk(MAX_PACKET as u16),
+            // 115200 8N1
+            line_coding: [0x00, 0xc2, 0x01, 0x00, 0, 0, 8],
+            dtr: false,
+        }
+    }
+
+    /// A terminal has the port open (DTR set)
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    /// Reads a received packet, `WouldBlock` if none
+    pub fn read(&mut self, buf: &mut [u8; MAX_PACKET]) -> Result<usize> {
+        self.read_ep.read(buf)
+    }
+
+    /// Writes a packet of up to `MAX_PACKET - 1` bytes of `data`, returns
+    /// the bytes written, `WouldBlock` if the endpoint is full
+    ///
+    /// A short packet ends the transfer, so the host passes on the data
+    /// without waiting for more.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+        let n = data.len().min(MAX_PACKET - 1);
+        self.write_ep.write(&data[..n])
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for SerialClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.iad(
+            self.comm_if,
+            2,
+            USB_CLASS_CDC,
+            CDC_SUBCLASS_ACM,
+            CDC_PROTOCOL_NONE,
+        )?;
+
+        writer.interface(
+            self.comm_if,
+            USB_CLASS_CDC,
+            CDC_SUBCLASS_ACM,
+            CDC_PROTOCOL_NONE,
+        )?;
+        // bcdCDC 1.10
+        writer.write(CS_INTERFACE, &[CDC_TYPE_HEADER, 0x10, 0x01])?;
+        // no call management, bDataInterface
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_CALL_MANAGEMENT, 0x00, self.data_if.into()],
+        )?;
+        // bmCapabilities, line coding and serial state
+        writer.write(CS_INTERFACE, &[CDC_TYPE_ACM, 0x02])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_UNION, self.comm_if.into(), self.data_if.into()],
+        )?;
+        writer.endpoint(&self.comm_ep)?;
+
+        writer.interface(self.data_if, USB_CLASS_CDC_DATA, 0x00, 0x00)?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.dtr = false;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.comm_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_GET_LINE_CODING if req.length == 7 => {
+                xfer.accept_with(&self.line_coding).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.comm_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_SEND_ENCAPSULATED_COMMAND | REQ_SEND_BREAK => {
+                xfer.accept().ok();
+            }
+            REQ_SET_LINE_CODING if xfer.data().len() >= 7 => {
+                self.line_coding.copy_from_slice(&xfer.data()[..7]);
+                xfer.accept().ok();
+            }
+            REQ_SET_CONTROL_LINE_STATE => {
+                // wValue, bit 0 DTR, bit 1 RTS
+                self.dtr = req.value & 0x01 != 0;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
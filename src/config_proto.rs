@@ -0,0 +1,518 @@
+//! Configuration protocol, carried in a vendor HID feature report
+//!
+//! The mouse interface has a vendor-defined (usage page 0xFF00) feature
+//! report with ID `REPORT_ID`, so desktop software can configure the mouse
+//! without drivers. The host writes a request (SET_REPORT, Feature), then
+//! reads back the response (GET_REPORT, Feature):
+//!
+//! request  [VERSION, command, payload..]
+//! response [VERSION, command, status, payload..]
+//!
+//! zero padded to `REPORT_SIZE`, multi-byte values little-endian. A request
+//! of another protocol version is answered with `Status::BadVersion`.
+//!
+//! The stored configuration (`Config`) uses the same encoding.
+//!
+//! Target independent, shared with the host tools (`host/src/bin/mousecfg.rs`),
+//! so the firmware and the tool stay in lockstep. Changes to the command set
+//! or the encoding bump `VERSION`.
+
+use crate::buttons::{Action, ButtonMap, BUTTONS};
+
+/// Protocol version
+pub const VERSION: u8 = 4;
+
+/// Feature report ID
+pub const REPORT_ID: u8 = 2;
+
+/// Feature report size, without the report ID
+pub const REPORT_SIZE: usize = 32;
+
+/// Resolution range, in steps of `CPI_STEP`
+pub const CPI_MIN: u16 = 50;
+pub const CPI_MAX: u16 = 16000;
+pub const CPI_STEP: u16 = 50;
+
+/// Number of DPI profiles
+pub const PROFILES: usize = 4;
+
+/// Polling intervals (bInterval) in ms
+pub const POLL_INTERVALS: [u8; 4] = [1, 2, 4, 8];
+
+// Command IDs
+const GET_VERSION: u8 = 0x01;
+const GET_CPI: u8 = 0x02;
+const SET_CPI: u8 = 0x03;
+const GET_BUTTON_MAP: u8 = 0x04;
+const SET_BUTTON_MAP: u8 = 0x05;
+const GET_POLL_INTERVAL: u8 = 0x06;
+const SET_POLL_INTERVAL: u8 = 0x07;
+const GET_LIFT: u8 = 0x08;
+const SET_LIFT: u8 = 0x09;
+const GET_LED: u8 = 0x0a;
+const SET_LED: u8 = 0x0b;
+const SAVE: u8 = 0x0c;
+const GET_PROFILES: u8 = 0x0d;
+const SET_PROFILES: u8 = 0x0e;
+const GET_DIAGNOSTICS: u8 = 0x0f;
+
+// Encoded size of an `Action`
+const ACTION_SIZE: usize = 3;
+
+/// Lift-off distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lift {
+    Mm2 = 0,
+    Mm3 = 1,
+}
+
+impl Lift {
+    /// The PMW3389 Lift_Config register value
+    pub fn register(self) -> u8 {
+        match self {
+            Lift::Mm2 => 0x02,
+            Lift::Mm3 => 0x03,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Lift> {
+        match v {
+            0 => Some(Lift::Mm2),
+            1 => Some(Lift::Mm3),
+            _ => None,
+        }
+    }
+}
+
+/// LED behaviour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LedMode {
+    Off = 0,
+    On = 1,
+    /// toggled by the buttons
+    Buttons = 2,
+}
+
+impl LedMode {
+    fn from_u8(v: u8) -> Option<LedMode> {
+        match v {
+            0 => Some(LedMode::Off),
+            1 => Some(LedMode::On),
+            2 => Some(LedMode::Buttons),
+            _ => None,
+        }
+    }
+}
+
+/// DPI profiles, the resolution is that of the active profile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Profiles {
+    pub active: u8,
+    pub cpi: [u16; PROFILES],
+}
+
+impl Profiles {
+    // [active, cpi..]
+    const SIZE: usize = 1 + 2 * PROFILES;
+
+    /// The resolution of the active profile
+    pub fn cpi(&self) -> u16 {
+        self.cpi[self.active as usize]
+    }
+
+    /// Sets the resolution of the active profile
+    pub fn set_cpi(&mut self, cpi: u16) {
+        self.cpi[self.active as usize] = cpi;
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.active;
+        for (bytes, cpi) in buf[1..Self::SIZE].chunks_mut(2).zip(self.cpi.iter()) {
+            bytes.copy_from_slice(&cpi.to_le_bytes());
+        }
+    }
+
+    fn decode(buf: &[u8]) -> Result<Profiles, Status> {
+        if buf.len() < Self::SIZE || buf[0] as usize >= PROFILES {
+            return Err(Status::BadArgument);
+        }
+        let mut cpi = [0; PROFILES];
+        for (cpi, bytes) in cpi.iter_mut().zip(buf[1..Self::SIZE].chunks(2)) {
+            *cpi = u16::from_le_bytes([bytes[0], bytes[1]]);
+            if !valid_cpi(*cpi) {
+                return Err(Status::BadArgument);
+            }
+        }
+        Ok(Profiles {
+            active: buf[0],
+            cpi,
+        })
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Profiles {
+            active: 0,
+            cpi: [300, 800, 1600, 3200],
+        }
+    }
+}
+
+/// Sensor and report rate diagnostics
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    pub connected: bool,
+    pub product_id: u8,
+    pub srom_id: u8,
+    /// Surface quality of the latest motion read
+    pub squal: u8,
+    /// Shutter of the latest motion read
+    pub shutter: u16,
+    /// Reports per second, see `report_stats::ReportStats`
+    pub report_rate: u16,
+    /// Polling intervals without a report, with and without motion waiting
+    pub missed: u32,
+    pub empty: u32,
+}
+
+impl Diagnostics {
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.connected as u8;
+        buf[1] = self.product_id;
+        buf[2] = self.srom_id;
+        buf[3] = self.squal;
+        buf[4..6].copy_from_slice(&self.shutter.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.report_rate.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.missed.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.empty.to_le_bytes());
+    }
+
+    fn decode(buf: &[u8]) -> Result<Diagnostics, Status> {
+        if buf.len() < 16 {
+            return Err(Status::BadArgument);
+        }
+        Ok(Diagnostics {
+            connected: buf[0] != 0,
+            product_id: buf[1],
+            srom_id: buf[2],
+            squal: buf[3],
+            shutter: u16::from_le_bytes([buf[4], buf[5]]),
+            report_rate: u16::from_le_bytes([buf[6], buf[7]]),
+            missed: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            empty: u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    GetVersion,
+    /// The resolution of the active profile
+    GetCpi,
+    SetCpi(u16),
+    GetProfiles,
+    SetProfiles(Profiles),
+    GetButtonMap,
+    SetButtonMap(ButtonMap),
+    GetPollInterval,
+    /// bInterval in ms, applied on the next enumeration
+    SetPollInterval(u8),
+    GetLift,
+    SetLift(Lift),
+    GetLed,
+    SetLed(LedMode),
+    /// Stores the current configuration in flash
+    Save,
+    GetDiagnostics,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response {
+    Version(u8),
+    Cpi(u16),
+    Profiles(Profiles),
+    ButtonMap(ButtonMap),
+    PollInterval(u8),
+    Lift(Lift),
+    Led(LedMode),
+    Diagnostics(Diagnostics),
+    /// A set command (or `Save`) was carried out
+    Done,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Ok = 0,
+    /// The request is not yet handled, read again
+    Pending = 1,
+    BadVersion = 2,
+    UnknownCommand = 3,
+    BadArgument = 4,
+    /// The command failed on the device, e.g., flash programming
+    Failed = 5,
+}
+
+impl Status {
+    fn from_u8(v: u8) -> Status {
+        match v {
+            0 => Status::Ok,
+            1 => Status::Pending,
+            2 => Status::BadVersion,
+            3 => Status::UnknownCommand,
+            4 => Status::BadArgument,
+            _ => Status::Failed,
+        }
+    }
+}
+
+impl Command {
+    pub fn id(&self) -> u8 {
+        match self {
+            Command::GetVersion => GET_VERSION,
+            Command::GetCpi => GET_CPI,
+            Command::SetCpi(_) => SET_CPI,
+            Command::GetProfiles => GET_PROFILES,
+            Command::SetProfiles(_) => SET_PROFILES,
+            Command::GetButtonMap => GET_BUTTON_MAP,
+            Command::SetButtonMap(_) => SET_BUTTON_MAP,
+            Command::GetPollInterval => GET_POLL_INTERVAL,
+            Command::SetPollInterval(_) => SET_POLL_INTERVAL,
+            Command::GetLift => GET_LIFT,
+            Command::SetLift(_) => SET_LIFT,
+            Command::GetLed => GET_LED,
+            Command::SetLed(_) => SET_LED,
+            Command::Save => SAVE,
+            Command::GetDiagnostics => GET_DIAGNOSTICS,
+        }
+    }
+
+    /// Encodes a request
+    pub fn encode(&self) -> [u8; REPORT_SIZE] {
+        let mut buf = [0; REPORT_SIZE];
+        buf[0] = VERSION;
+        buf[1] = self.id();
+        let payload = &mut buf[2..];
+        match *self {
+            Command::SetCpi(cpi) => payload[..2].copy_from_slice(&cpi.to_le_bytes()),
+            Command::SetProfiles(profiles) => profiles.encode(payload),
+            Command::SetButtonMap(map) => encode_map(&map, payload),
+            Command::SetPollInterval(ms) => payload[0] = ms,
+            Command::SetLift(lift) => payload[0] = lift as u8,
+            Command::SetLed(led) => payload[0] = led as u8,
+            _ => {}
+        }
+        buf
+    }
+
+    /// Decodes a request, the error is the status to respond with
+    pub fn decode(buf: &[u8]) -> Result<Command, Status> {
+        if buf.len() < 2 || buf[0] != VERSION {
+            return Err(Status::BadVersion);
+        }
+        let payload = &buf[2..];
+        let arg = |n: usize| payload.get(n).copied().ok_or(Status::BadArgument);
+        Ok(match buf[1] {
+            GET_VERSION => Command::GetVersion,
+            GET_CPI => Command::GetCpi,
+            SET_CPI => {
+                let cpi = u16::from_le_bytes([arg(0)?, arg(1)?]);
+                if !valid_cpi(cpi) {
+                    return Err(Status::BadArgument);
+                }
+                Command::SetCpi(cpi)
+            }
+            GET_PROFILES => Command::GetProfiles,
+            SET_PROFILES => Command::SetProfiles(Profiles::decode(payload)?),
+            GET_BUTTON_MAP => Command::GetButtonMap,
+            SET_BUTTON_MAP => Command::SetButtonMap(decode_map(payload)?),
+            GET_POLL_INTERVAL => Command::GetPollInterval,
+            SET_POLL_INTERVAL => match arg(0)? {
+                ms if valid_poll_interval(ms) => Command::SetPollInterval(ms),
+                _ => return Err(Status::BadArgument),
+            },
+            GET_LIFT => Command::GetLift,
+            SET_LIFT => Command::SetLift(Lift::from_u8(arg(0)?).ok_or(Status::BadArgument)?),
+            GET_LED => Command::GetLed,
+            SET_LED => Command::SetLed(LedMode::from_u8(arg(0)?).ok_or(Status::BadArgument)?),
+            SAVE => Command::Save,
+            GET_DIAGNOSTICS => Command::GetDiagnostics,
+            _ => return Err(Status::UnknownCommand),
+        })
+    }
+}
+
+/// Encodes the response to the command `id`
+pub fn encode_response(id: u8, result: &Result<Response, Status>) -> [u8; REPORT_SIZE] {
+    let mut buf = [0; REPORT_SIZE];
+    buf[0] = VERSION;
+    buf[1] = id;
+    let response = match result {
+        Ok(response) => response,
+        Err(status) => {
+            buf[2] = *status as u8;
+            return buf;
+        }
+    };
+    buf[2] = Status::Ok as u8;
+    let payload = &mut buf[3..];
+    match *response {
+        Response::Version(v) => payload[0] = v,
+        Response::Cpi(cpi) => payload[..2].copy_from_slice(&cpi.to_le_bytes()),
+        Response::Profiles(profiles) => profiles.encode(payload),
+        Response::ButtonMap(map) => encode_map(&map, payload),
+        Response::PollInterval(ms) => payload[0] = ms,
+        Response::Lift(lift) => payload[0] = lift as u8,
+        Response::Led(led) => payload[0] = led as u8,
+        Response::Diagnostics(diag) => diag.encode(payload),
+        Response::Done => {}
+    }
+    buf
+}
+
+/// Decodes a response, returns the command ID and the response (or the
+/// status of a failed request)
+pub fn decode_response(buf: &[u8]) -> (u8, Result<Response, Status>) {
+    if buf.len() < 3 || buf[0] != VERSION {
+        return (0, Err(Status::BadVersion));
+    }
+    let id = buf[1];
+    let status = Status::from_u8(buf[2]);
+    if status != Status::Ok {
+        return (id, Err(status));
+    }
+    let payload = &buf[3..];
+    let arg = |n: usize| payload.get(n).copied().ok_or(Status::BadArgument);
+    let response = || {
+        Ok(match id {
+            GET_VERSION => Response::Version(arg(0)?),
+            GET_CPI => Response::Cpi(u16::from_le_bytes([arg(0)?, arg(1)?])),
+            GET_PROFILES => Response::Profiles(Profiles::decode(payload)?),
+            GET_BUTTON_MAP => Response::ButtonMap(decode_map(payload)?),
+            GET_POLL_INTERVAL => Response::PollInterval(arg(0)?),
+            GET_LIFT => Response::Lift(Lift::from_u8(arg(0)?).ok_or(Status::BadArgument)?),
+            GET_LED => Response::Led(LedMode::from_u8(arg(0)?).ok_or(Status::BadArgument)?),
+            GET_DIAGNOSTICS => Response::Diagnostics(Diagnostics::decode(payload)?),
+            _ => Response::Done,
+        })
+    };
+    (id, response())
+}
+
+/// One of `POLL_INTERVALS`
+pub fn valid_poll_interval(ms: u8) -> bool {
+    POLL_INTERVALS.contains(&ms)
+}
+
+/// A resolution in range, in whole steps
+pub fn valid_cpi(cpi: u16) -> bool {
+    (CPI_MIN..=CPI_MAX).contains(&cpi) && cpi / CPI_STEP * CPI_STEP == cpi
+}
+
+// [kind, a, b] per button, kind 0 none, 1 mouse (a = bit), 2 key (a =
+// modifiers, b = usage ID), 3 scroll
+fn encode_map(map: &ButtonMap, buf: &mut [u8]) {
+    for (n, bytes) in buf.chunks_mut(ACTION_SIZE).take(BUTTONS).enumerate() {
+        let action = match map.action(n) {
+            Action::None => [0, 0, 0],
+            Action::Mouse(bit) => [1, bit, 0],
+            Action::Key { modifiers, key } => [2, modifiers, key],
+            Action::Scroll => [3, 0, 0],
+        };
+        bytes.copy_from_slice(&action);
+    }
+}
+
+fn decode_map(buf: &[u8]) -> Result<ButtonMap, Status> {
+    if buf.len() < BUTTONS * ACTION_SIZE {
+        return Err(Status::BadArgument);
+    }
+    let mut map = ButtonMap::default();
+    for (n, bytes) in buf.chunks(ACTION_SIZE).take(BUTTONS).enumerate() {
+        let action = match bytes[0] {
+            0 => Action::None,
+            1 => Action::Mouse(bytes[1]),
+            2 => Action::Key {
+                modifiers: bytes[1],
+                key: bytes[2],
+            },
+            3 => Action::Scroll,
+            _ => return Err(Status::BadArgument),
+        };
+        // mouse buttons and keys within the reports
+        map.set(n, action).map_err(|_| Status::BadArgument)?;
+    }
+    Ok(map)
+}
+
+// Magic of a stored configuration, "MC"
+const MAGIC: [u8; 2] = [0x4d, 0x43];
+
+/// The configuration, as stored in flash
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub profiles: Profiles,
+    pub buttons: ButtonMap,
+    /// bInterval in ms
+    pub poll_ms: u8,
+    pub lift: Lift,
+    pub led: LedMode,
+}
+
+impl Config {
+    pub const SIZE: usize = 32;
+
+    /// [MAGIC, VERSION, profiles.., poll_ms, lift, led, map.., checksum]
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..2].copy_from_slice(&MAGIC);
+        buf[2] = VERSION;
+        self.profiles.encode(&mut buf[3..]);
+        buf[12] = self.poll_ms;
+        buf[13] = self.lift as u8;
+        buf[14] = self.led as u8;
+        encode_map(&self.buttons, &mut buf[15..]);
+        buf[Self::SIZE - 1] = checksum(&buf[..Self::SIZE - 1]);
+        buf
+    }
+
+    /// Decodes a stored configuration, `None` if erased, corrupt or of
+    /// another version
+    pub fn from_bytes(buf: &[u8]) -> Option<Config> {
+        if buf.len() < Self::SIZE
+            || buf[..2] != MAGIC
+            || buf[2] != VERSION
+            || buf[Self::SIZE - 1] != checksum(&buf[..Self::SIZE - 1])
+        {
+            return None;
+        }
+        if !valid_poll_interval(buf[12]) {
+            return None;
+        }
+        Some(Config {
+            profiles: Profiles::decode(&buf[3..]).ok()?,
+            poll_ms: buf[12],
+            lift: Lift::from_u8(buf[13])?,
+            led: LedMode::from_u8(buf[14])?,
+            buttons: decode_map(&buf[15..]).ok()?,
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            profiles: Profiles::default(),
+            buttons: ButtonMap::default(),
+            poll_ms: 1,
+            lift: Lift::Mm2,
+            led: LedMode::Buttons,
+        }
+    }
+}
+
+// Two's complement of the byte sum, an erased (0xff) page fails the MAGIC
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)).wrapping_neg()
+}
+
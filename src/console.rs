@@ -0,0 +1,286 @@
+//! Text console, over the CDC-ACM serial port
+//!
+//! Lets a unit be debugged and configured with only a USB cable and a
+//! terminal (e.g., `picocom /dev/ttyACM0`). Lines are echoed, backspace
+//! edits, commands:
+//!
+//!   help                  the commands
+//!   version               configuration protocol version
+//!   cpi [CPI]             resolution of the active profile
+//!   poll [1|2|4|8]        polling interval in ms, on the next start
+//!   lift [2|3]            lift-off distance in mm
+//!   led [off|on|buttons]  LED mode
+//!   save                  stores the configuration in flash
+//!   diag                  sensor diagnostics, SQUAL and report rate
+//!   reg ADDR [VALUE]      reads or writes a sensor register
+//!   selftest              sensor SROM CRC test
+//!
+//! Settings map to the commands of `config_proto`, so they are carried out
+//! (and validated) as those of the feature report. Numbers are decimal or
+//! 0x hex.
+
+use core::fmt::{self, Write};
+
+use crate::config_proto::{Command, LedMode, Lift, Response, Status};
+use crate::pmw3389::Register;
+
+const LINE_SIZE: usize = 64;
+const OUT_SIZE: usize = 512;
+
+const PROMPT: &str = "> ";
+
+const HELP: &str = "help | version | cpi [CPI] | poll [1|2|4|8] | lift [2|3] | \
+                    led [off|on|buttons] | save | diag | reg ADDR [VALUE] | selftest\n";
+
+/// SROM CRC of a correctly loaded firmware
+pub const SROM_CRC: u16 = 0xbeef;
+
+/// A console line, to be carried out by the application
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Command(Command),
+    ReadRegister(u8),
+    WriteRegister(u8, u8),
+    SelfTest,
+}
+
+/// Output waiting to be sent, `\n` is sent as `\r\n`
+pub struct Output {
+    buf: [u8; OUT_SIZE],
+    len: usize,
+}
+
+impl Output {
+    /// The output not yet sent
+    pub fn pending(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Removes `n` bytes sent
+    pub fn consume(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.buf.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+
+    /// Prints the result of a command
+    pub fn response(&mut self, result: &Result<Response, Status>) {
+        let response = match result {
+            Ok(response) => response,
+            Err(status) => {
+                writeln!(self, "error {:?}", status).ok();
+                return;
+            }
+        };
+        match response {
+            Response::Version(v) => writeln!(self, "protocol version {}", v),
+            Response::Cpi(cpi) => writeln!(self, "{} cpi", cpi),
+            Response::PollInterval(ms) => writeln!(self, "{} ms", ms),
+            Response::Lift(Lift::Mm2) => writeln!(self, "2 mm"),
+            Response::Lift(Lift::Mm3) => writeln!(self, "3 mm"),
+            Response::Led(led) => writeln!(self, "{:?}", led),
+            Response::Diagnostics(d) => writeln!(
+                self,
+                "connected {}, product id 0x{:02x}, srom id 0x{:02x}\n\
+                 squal {}, shutter {}\n\
+                 report rate {}/s, missed {}, empty {}",
+                d.connected,
+                d.product_id,
+                d.srom_id,
+                d.squal,
+                d.shutter,
+                d.report_rate,
+                d.missed,
+                d.empty
+            ),
+            Response::Done => writeln!(self, "ok"),
+            // not requested from the console
+            Response::Profiles(_) | Response::ButtonMap(_) => Ok(()),
+        }
+        .ok();
+    }
+
+    /// Prints a sensor register
+    pub fn register(&mut self, addr: u8, value: u8) {
+        match Register::from_addr(addr) {
+            Some(reg) => writeln!(self, "0x{:02x} {:?} = 0x{:02x}", addr, reg, value),
+            None => writeln!(self, "0x{:02x} = 0x{:02x}", addr, value),
+        }
+        .ok();
+    }
+
+    /// Prints the outcome of the SROM CRC test
+    pub fn self_test(&mut self, crc: u16) {
+        let verdict = if crc == SROM_CRC { "passed" } else { "FAILED" };
+        writeln!(self, "srom crc 0x{:04x}, {}", crc, verdict).ok();
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.len < OUT_SIZE {
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+    }
+}
+
+impl Write for Output {
+    // Truncates on overflow
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            if b == b'\n' {
+                self.push(b'\r');
+            }
+            self.push(b);
+        }
+        Ok(())
+    }
+}
+
+pub struct Console {
+    line: [u8; LINE_SIZE],
+    len: usize,
+    // the previous character ended a line with CR, a following LF is skipped
+    cr: bool,
+    out: Output,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let mut console = Console {
+            line: [0; LINE_SIZE],
+            len: 0,
+            cr: false,
+            out: Output {
+                buf: [0; OUT_SIZE],
+                len: 0,
+            },
+        };
+        console.out.write_str(PROMPT).ok();
+        console
+    }
+
+    /// The output, to be sent and consumed by the application
+    pub fn output(&mut self) -> &mut Output {
+        &mut self.out
+    }
+
+    /// Takes received characters, `run` carries out each complete line
+    /// and prints its outcome
+    pub fn receive<F>(&mut self, data: &[u8], mut run: F)
+    where
+        F: FnMut(Input, &mut Output),
+    {
+        for &b in data {
+            let cr = core::mem::replace(&mut self.cr, b == b'\r');
+            match b {
+                b'\n' if cr => {}
+                b'\r' | b'\n' => {
+                    self.out.write_str("\n").ok();
+                    // only printable characters are kept
+                    let line = core::str::from_utf8(&self.line[..self.len]).unwrap_or("");
+                    match parse(line) {
+                        Ok(Some(input)) => run(input, &mut self.out),
+                        Ok(None) => {}
+                        Err(e) => {
+                            self.out.write_str(e).ok();
+                        }
+                    }
+                    self.len = 0;
+                    self.out.write_str(PROMPT).ok();
+                }
+                // backspace, DEL
+                0x08 | 0x7f if self.len > 0 => {
+                    self.len -= 1;
+                    self.out.write_str("\x08 \x08").ok();
+                }
+                b if (0x20..0x7f).contains(&b) && self.len < LINE_SIZE => {
+                    self.line[self.len] = b;
+                    self.len += 1;
+                    self.out.push(b);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A line, `None` if empty, `Err` the message to print instead (or the help)
+fn parse(line: &str) -> Result<Option<Input>, &'static str> {
+    const BAD_ARGUMENT: &str = "bad argument\n";
+
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let mut args = [""; 2];
+    let mut n = 0;
+    for word in words {
+        if n == args.len() {
+            return Err(BAD_ARGUMENT);
+        }
+        args[n] = word;
+        n += 1;
+    }
+    let args = &args[..n];
+
+    let input = match (name, args) {
+        ("help", []) => return Err(HELP),
+        ("version", []) => Input::Command(Command::GetVersion),
+        ("cpi", []) => Input::Command(Command::GetCpi),
+        ("cpi", [cpi]) => Input::Command(Command::SetCpi(number(cpi).ok_or(BAD_ARGUMENT)?)),
+        ("poll", []) => Input::Command(Command::GetPollInterval),
+        ("poll", [ms]) => {
+            let ms = number(ms).filter(|ms| *ms <= 0xff).ok_or(BAD_ARGUMENT)?;
+            Input::Command(Command::SetPollInterval(ms as u8))
+        }
+        ("lift", []) => Input::Command(Command::GetLift),
+        ("lift", ["2"]) => Input::Command(Command::SetLift(Lift::Mm2)),
+        ("lift", ["3"]) => Input::Command(Command::SetLift(Lift::Mm3)),
+        ("led", []) => Input::Command(Command::GetLed),
+        ("led", ["off"]) => Input::Command(Command::SetLed(LedMode::Off)),
+        ("led", ["on"]) => Input::Command(Command::SetLed(LedMode::On)),
+        ("led", ["buttons"]) => Input::Command(Command::SetLed(LedMode::Buttons)),
+        ("save", []) => Input::Command(Command::Save),
+        ("diag", []) => Input::Command(Command::GetDiagnostics),
+        ("reg", [addr]) => Input::ReadRegister(register(addr)?),
+        ("reg", [addr, value]) => {
+            let value = number(value).filter(|v| *v <= 0xff).ok_or(BAD_ARGUMENT)?;
+            Input::WriteRegister(register(addr)?, value as u8)
+        }
+        ("selftest", []) => Input::SelfTest,
+        ("cpi", _) | ("poll", _) | ("lift", _) | ("led", _) | ("reg", _) => {
+            return Err(BAD_ARGUMENT)
+        }
+        _ => return Err("unknown command, see help\n"),
+    };
+
+    // validated as a request of the feature report
+    if let Input::Command(command) = input {
+        if Command::decode(&command.encode()) != Ok(command) {
+            return Err(BAD_ARGUMENT);
+        }
+    }
+    Ok(Some(input))
+}
+
+// A register address, 7 bits
+fn register(s: &str) -> Result<u8, &'static str> {
+    match number(s) {
+        Some(addr) if addr < 0x80 => Ok(addr as u8),
+        _ => Err("bad register address\n"),
+    }
+}
+
+fn number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
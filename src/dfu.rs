@@ -0,0 +1,174 @@
+//! USB DFU runtime class, reboot into the ROM bootloader
+//!
+//! A DFU 1.1 runtime interface (no endpoints, requests on EP0). On
+//! DFU_DETACH the application calls `enter_bootloader`, which flags the
+//! request in a RTC backup register and resets. Early at the next start
+//! (`pre_init`), `boot_if_requested` clears the flag and jumps to the ROM
+//! bootloader (AN2606, system memory at 0x1FFF_0000), which enumerates as
+//! the ST DFU device (0483:df11):
+//!
+//! > dfu-util -d c410:0000 -e
+//! > dfu-util -d 0483:df11 -a 0 -s 0x08000000:leave -D app.bin
+//!
+//! The backup registers keep their value over a system reset, but not a
+//! power cycle, so a stale flag can't trap the mouse in the bootloader.
+
+use cortex_m::peripheral::SCB;
+use stm32f4xx_hal::stm32::{PWR, RCC, RTC};
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+const USB_CLASS_APPLICATION_SPECIFIC: u8 = 0xfe;
+const DFU_SUBCLASS: u8 = 0x01;
+const DFU_PROTOCOL_RUNTIME: u8 = 0x01;
+
+const DESC_DFU_FUNCTIONAL: u8 = 0x21;
+
+const REQ_DETACH: u8 = 0x00;
+const REQ_GETSTATUS: u8 = 0x03;
+const REQ_GETSTATE: u8 = 0x05;
+
+// bmAttributes, bitWillDetach | bitCanUpload | bitCanDnload
+const ATTRIBUTES: u8 = 0x0b;
+// wDetachTimeOut, in ms
+const DETACH_TIMEOUT: u16 = 1000;
+// wTransferSize, as of the ROM bootloader
+const TRANSFER_SIZE: u16 = 2048;
+
+// bState
+const STATE_APP_IDLE: u8 = 0;
+
+// System memory, the vector table of the ROM bootloader
+const SYSTEM_MEMORY: u32 = 0x1fff_0000;
+
+// Request in RTC_BKP0R, "DFU!"
+const MAGIC: u32 = 0x4446_5521;
+
+pub struct DfuRuntimeClass {
+    dfu_if: InterfaceNumber,
+    // DFU_DETACH received, not yet taken
+    detach: bool,
+}
+
+impl DfuRuntimeClass {
+    /// Creates a new DFU runtime interface
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>) -> DfuRuntimeClass {
+        DfuRuntimeClass {
+            dfu_if: alloc.interface(),
+            detach: false,
+        }
+    }
+
+    /// Takes a DFU_DETACH request
+    pub fn take_detach(&mut self) -> bool {
+        core::mem::replace(&mut self.detach, false)
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for DfuRuntimeClass {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(
+            self.dfu_if,
+            USB_CLASS_APPLICATION_SPECIFIC,
+            DFU_SUBCLASS,
+            DFU_PROTOCOL_RUNTIME,
+        )?;
+        let timeout = DETACH_TIMEOUT.to_le_bytes();
+        let size = TRANSFER_SIZE.to_le_bytes();
+        writer.write(
+            DESC_DFU_FUNCTIONAL,
+            &[
+                ATTRIBUTES, // bmAttributes
+                timeout[0], // wDetachTimeOut
+                timeout[1],
+                size[0], // wTransferSize
+                size[1],
+                0x1a, // bcdDFUVersion (1.1a)
+                0x01,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.detach = false;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.dfu_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_GETSTATUS => {
+                // bStatus OK, bwPollTimeout, bState, iString
+                xfer.accept_with(&[0, 0, 0, 0, STATE_APP_IDLE, 0]).ok();
+            }
+            REQ_GETSTATE => {
+                xfer.accept_with(&[STATE_APP_IDLE]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.dfu_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_DETACH => {
+                self.detach = true;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
+
+/// Flags the bootloader request and resets
+pub fn enter_bootloader() -> ! {
+    unsafe {
+        enable_backup();
+        (*RTC::ptr()).bkp0r.write(|w| w.bits(MAGIC));
+    }
+    SCB::sys_reset()
+}
+
+/// Jumps to the ROM bootloader if requested by `enter_bootloader`, to be
+/// called first at start, with the peripherals in their reset state
+///
+/// # Safety
+///
+/// Takes over the PWR and RTC registers, and doesn't return if the
+/// bootloader was requested.
+pub unsafe fn boot_if_requested() {
+    let rtc = &*RTC::ptr();
+    if rtc.bkp0r.read().bits() != MAGIC {
+        return;
+    }
+    enable_backup();
+    rtc.bkp0r.write(|w| w.bits(0));
+    cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32)
+}
+
+// PWR clock and write access to the backup domain (DBP)
+unsafe fn enable_backup() {
+    (*RCC::ptr()).apb1enr.modify(|_, w| w.pwren().set_bit());
+    (*PWR::ptr()).cr.modify(|_, w| w.dbp().set_bit());
+}
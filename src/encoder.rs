@@ -0,0 +1,155 @@
+//! Quadrature decoder for a mechanical scroll wheel encoder
+//!
+//! Two sources are supported:
+//!
+//! - `Quadrature`, the A/B lines sampled at a fixed rate (GPIO polling or
+//!   EXTI on both edges). Each line is debounced, a state is taken once it
+//!   is stable for a number of samples. Transitions skipping a state (both
+//!   lines changed) are ignored.
+//! - `Counter`, a timer in encoder mode (SMS = 0b011, counting every edge
+//!   of TI1 and TI2), debounced by the input filter (ICxF) of the timer.
+//!
+//! Mechanical encoders rest in a detent every 4 transitions (a full cycle)
+//! or every 2 (half cycle). Steps are counted in whole detents, a partial
+//! step is kept until completed. `Quadrature` re-aligns the count when the
+//! wheel comes to rest in a detent, so a missed transition doesn't offset
+//! the following detents.
+
+// Steps by the previous and the current state (A << 1 | B), forward is
+// A leading B, 00 -> 10 -> 11 -> 01 -> 00
+const STEPS: [i8; 16] = [
+    0, -1, 1, 0, // from 00
+    1, 0, 0, -1, // from 01
+    -1, 0, 0, 1, // from 10
+    0, 1, -1, 0, // from 11
+];
+
+/// Transitions counted into detents
+struct Detents {
+    per_detent: i32,
+    // transitions not yet taken
+    count: i32,
+}
+
+impl Detents {
+    fn new(per_detent: u8) -> Self {
+        Detents {
+            per_detent: per_detent.max(1) as i32,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, transitions: i32) {
+        self.count = self.count.saturating_add(transitions);
+    }
+
+    // Rounds the count to the nearest detent
+    fn align(&mut self) {
+        let half = self.per_detent / 2;
+        let offset = (self.count + half).rem_euclid(self.per_detent) - half;
+        self.count -= offset;
+    }
+
+    fn take(&mut self) -> i32 {
+        let detents = self.count / self.per_detent;
+        self.count -= detents * self.per_detent;
+        detents
+    }
+}
+
+/// Decoder of sampled A/B lines
+pub struct Quadrature {
+    // debounced state, A << 1 | B
+    state: u8,
+    // the state in a detent
+    rest: u8,
+    // the last sampled state, and for how many samples in a row
+    sampled: u8,
+    stable: u8,
+    // samples for a state to be taken
+    debounce: u8,
+    detents: Detents,
+}
+
+impl Quadrature {
+    /// A decoder with the wheel resting in a detent at the current `a`,
+    /// `b` levels, with `transitions_per_detent` 4 (full cycle) or 2 (half
+    /// cycle), and a state taken when sampled `debounce` times in a row
+    pub fn new(a: bool, b: bool, transitions_per_detent: u8, debounce: u8) -> Self {
+        let state = (a as u8) << 1 | b as u8;
+        Quadrature {
+            state,
+            rest: state,
+            sampled: state,
+            stable: 0,
+            debounce: debounce.max(1),
+            detents: Detents::new(transitions_per_detent),
+        }
+    }
+
+    /// Samples the lines, to be called at a fixed rate (e.g., 1 ms)
+    pub fn update(&mut self, a: bool, b: bool) {
+        let sampled = (a as u8) << 1 | b as u8;
+        if sampled != self.sampled {
+            self.sampled = sampled;
+            self.stable = 0;
+        }
+        self.stable = self.stable.saturating_add(1);
+        if self.stable < self.debounce || sampled == self.state {
+            return;
+        }
+
+        let step = STEPS[(self.state << 2 | sampled) as usize];
+        self.state = sampled;
+        self.detents.add(step as i32);
+        if self.in_detent() {
+            self.detents.align();
+        }
+    }
+
+    // The current state is a resting position
+    fn in_detent(&self) -> bool {
+        match self.detents.per_detent {
+            // every other state, 00 and 11 (or 01 and 10)
+            2 => self.state == self.rest || self.state == self.rest ^ 0b11,
+            4 => self.state == self.rest,
+            _ => false,
+        }
+    }
+
+    /// Takes the whole detents turned, positive forward (swap A and B to
+    /// reverse)
+    pub fn take_detents(&mut self) -> i32 {
+        self.detents.take()
+    }
+}
+
+/// Decoder of a timer counter in encoder mode
+pub struct Counter {
+    last: u16,
+    detents: Detents,
+}
+
+impl Counter {
+    /// A decoder starting at the counter value `cnt`, counting
+    /// `transitions_per_detent` counts per detent (4 in mode 3, 2 in mode
+    /// 1 or 2 for a full cycle encoder)
+    pub fn new(cnt: u16, transitions_per_detent: u8) -> Self {
+        Counter {
+            last: cnt,
+            detents: Detents::new(transitions_per_detent),
+        }
+    }
+
+    /// Reads the counter (TIMx_CNT, 16 bit, ARR = 0xffff), at least once
+    /// per 32767 counts
+    pub fn update(&mut self, cnt: u16) {
+        self.detents.add(cnt.wrapping_sub(self.last) as i16 as i32);
+        self.last = cnt;
+    }
+
+    /// Takes the whole detents turned, positive when counting up
+    pub fn take_detents(&mut self) -> i32 {
+        self.detents.take()
+    }
+}
@@ -0,0 +1,110 @@
+//! Configuration storage in flash
+//!
+//! The configuration (`config_proto::Config`) is kept at the start of
+//! sector 7 (0x0806_0000, 128K), the last of the 512K flash, just beyond
+//! the program (the FLASH region of memory.x, 384K).
+//! Saving erases the whole sector, which stalls the CPU (and USB) for
+//! 1-2 s, so it should only be done on request.
+
+use core::ptr;
+
+use stm32f4xx_hal::stm32::FLASH;
+
+use crate::config_proto::Config;
+
+/// Start of sector 7
+pub const ADDR: u32 = 0x0806_0000;
+const SECTOR: u8 = 7;
+const SECTOR_SIZE: u32 = 128 * 1024;
+
+// End of the FLASH region of memory.x (the program), and of the flash
+const PROGRAM_END: u32 = 0x0800_0000 + 384 * 1024;
+const FLASH_END: u32 = 0x0800_0000 + 512 * 1024;
+
+// Saving erases neither the program nor past the end of the flash
+const _: () = assert!(ADDR >= PROGRAM_END && ADDR + SECTOR_SIZE <= FLASH_END);
+
+// FLASH_KEYR unlock sequence
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+// FLASH_SR error flags, PGSERR, PGPERR, PGAERR, WRPERR, OPERR
+const SR_ERRORS: u32 = 0xf2;
+
+/// Erase or programming failed, the FLASH_SR error flags
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Error(pub u32);
+
+pub struct ConfigFlash {
+    flash: FLASH,
+}
+
+impl ConfigFlash {
+    pub fn new(flash: FLASH) -> Self {
+        ConfigFlash { flash }
+    }
+
+    /// The stored configuration, `None` if erased or corrupt
+    pub fn read(&self) -> Option<Config> {
+        let mut buf = [0; Config::SIZE];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { ptr::read_volatile((ADDR as usize + i) as *const u8) };
+        }
+        Config::from_bytes(&buf)
+    }
+
+    /// Erases the sector and stores `config`
+    pub fn write(&mut self, config: &Config) -> Result<(), Error> {
+        self.unlock();
+        let result = self.erase().and_then(|_| self.program(&config.to_bytes()));
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+        result
+    }
+
+    fn unlock(&mut self) {
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| unsafe { w.key().bits(KEY1) });
+            self.flash.keyr.write(|w| unsafe { w.key().bits(KEY2) });
+        }
+    }
+
+    fn erase(&mut self) -> Result<(), Error> {
+        self.wait()?;
+        self.flash
+            .cr
+            .modify(|_, w| unsafe { w.psize().bits(0).snb().bits(SECTOR) }.ser().set_bit());
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.ser().clear_bit());
+        result
+    }
+
+    // byte wide (PSIZE x8), valid at any supply voltage
+    fn program(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.wait()?;
+        self.flash
+            .cr
+            .modify(|_, w| unsafe { w.psize().bits(0) }.pg().set_bit());
+        let mut result = Ok(());
+        for (i, b) in data.iter().enumerate() {
+            unsafe { ptr::write_volatile((ADDR as usize + i) as *mut u8, *b) };
+            result = self.wait();
+            if result.is_err() {
+                break;
+            }
+        }
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        result
+    }
+
+    // Waits while busy, reports (and clears) the error flags
+    fn wait(&mut self) -> Result<(), Error> {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+        let errors = self.flash.sr.read().bits() & SR_ERRORS;
+        if errors != 0 {
+            self.flash.sr.write(|w| unsafe { w.bits(errors) });
+            return Err(Error(errors));
+        }
+        Ok(())
+    }
+}
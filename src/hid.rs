@@ -0,0 +1,508 @@
+//! USB HID mouse class
+//!
+//! A mouse interface with 5 buttons, 16-bit relative X/Y, a wheel and
+//! horizontal pan (AC Pan). Motion from the PMW3389 (up to 16000 CPI)
+//! reaches the host without clipping to the +-127 of a basic mouse.
+//!
+//! The interface is in the boot subclass, a host without a HID parser
+//! (BIOS/UEFI setup, KVM switches) selects the boot protocol by
+//! SET_PROTOCOL and gets the fixed 3 byte boot report (3 buttons, i8 X/Y).
+//!
+//! The idle rate (SET_IDLE) is kept per report ID, `tick` re-sends the
+//! button state when the idle period expires. GET_REPORT answers with the
+//! current button state.
+//!
+//! Wheel and pan each have a Resolution Multiplier (feature report
+//! `RES_REPORT_ID`), a host supporting it (Windows 8+, Linux 5.0+) enables
+//! high-resolution scrolling, 1/`HIRES_MULTIPLIER` detent per unit. See
+//! `resolution_multiplier` and `scroll::Scroll`.
+//!
+//! A vendor feature report (`config_proto::REPORT_ID`) carries the
+//! configuration protocol, requests are picked up by the application with
+//! `take_config_request`. The mouse input report has the ID `REPORT_ID`.
+//!
+//! Replaces the hand-written class of the rtt_rtic_usb_mouse example
+//! (and `usbd_hid` in Project_Mouse).
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+use crate::config_proto;
+
+pub const USB_CLASS_HID: u8 = 0x03;
+
+pub(crate) const USB_SUBCLASS_BOOT: u8 = 0x01;
+
+const USB_INTERFACE_MOUSE: u8 = 0x02;
+
+pub(crate) const REQ_GET_REPORT: u8 = 0x01;
+pub(crate) const REQ_GET_IDLE: u8 = 0x02;
+pub(crate) const REQ_GET_PROTOCOL: u8 = 0x03;
+pub(crate) const REQ_SET_REPORT: u8 = 0x09;
+pub(crate) const REQ_SET_IDLE: u8 = 0x0a;
+pub(crate) const REQ_SET_PROTOCOL: u8 = 0x0b;
+
+pub(crate) const REPORT_TYPE_INPUT: u8 = 0x01;
+pub(crate) const REPORT_TYPE_OUTPUT: u8 = 0x02;
+pub(crate) const REPORT_TYPE_FEATURE: u8 = 0x03;
+
+/// Input report ID
+pub const REPORT_ID: u8 = 1;
+
+// Input report IDs, 0 (all reports) and REPORT_ID
+const REPORT_IDS: usize = 2;
+
+/// Feature report ID of the wheel and pan resolution multipliers
+pub const RES_REPORT_ID: u8 = 3;
+
+pub use crate::scroll::HIRES_MULTIPLIER;
+
+pub(crate) const DESC_HID: u8 = 0x21;
+pub(crate) const DESC_REPORT: u8 = 0x22;
+
+// https://docs.microsoft.com/en-us/windows-hardware/design/component-guidelines/mouse-collection-report-descriptor
+pub const REPORT_DESCR: &[u8] = &[
+    0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+    0x09, 0x02, // USAGE (Mouse)
+    0xa1, 0x01, // COLLECTION (Application)
+    0x85, REPORT_ID, //   REPORT_ID (1)
+    0x09, 0x01, //   USAGE (Pointer)
+    0xa1, 0x00, //   COLLECTION (Physical)
+    0x05, 0x09, //     USAGE_PAGE (Button)
+    0x19, 0x01, //     USAGE_MINIMUM (Button 1)
+    0x29, 0x05, //     USAGE_MAXIMUM (Button 5)
+    0x15, 0x00, //     LOGICAL_MINIMUM (0)
+    0x25, 0x01, //     LOGICAL_MAXIMUM (1)
+    0x95, 0x05, //     REPORT_COUNT (5)
+    0x75, 0x01, //     REPORT_SIZE (1)
+    0x81, 0x02, //     INPUT (Data,Var,Abs)
+    0x95, 0x01, //     REPORT_COUNT (1)
+    0x75, 0x03, //     REPORT_SIZE (3)
+    0x81, 0x03, //     INPUT (Cnst,Var,Abs)
+    0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+    0x09, 0x30, //     USAGE (X)
+    0x09, 0x31, //     USAGE (Y)
+    0x16, 0x01, 0x80, //     LOGICAL_MINIMUM (-32767)
+    0x26, 0xff, 0x7f, //     LOGICAL_MAXIMUM (32767)
+    0x75, 0x10, //     REPORT_SIZE (16)
+    0x95, 0x02, //     REPORT_COUNT (2)
+    0x81, 0x06, //     INPUT (Data,Var,Rel)
+    0xa1, 0x02, //     COLLECTION (Logical)
+    0x85, RES_REPORT_ID, //       REPORT_ID (3)
+    0x09, 0x48, //       USAGE (Resolution Multiplier)
+    0x15, 0x00, //       LOGICAL_MINIMUM (0)
+    0x25, 0x01, //       LOGICAL_MAXIMUM (1)
+    0x35, 0x01, //       PHYSICAL_MINIMUM (1)
+    0x45, HIRES_MULTIPLIER, //       PHYSICAL_MAXIMUM (8)
+    0x75, 0x02, //       REPORT_SIZE (2)
+    0x95, 0x01, //       REPORT_COUNT (1)
+    0xb1, 0x02, //       FEATURE (Data,Var,Abs)
+    0x35, 0x00, //       PHYSICAL_MINIMUM (0)
+    0x45, 0x00, //       PHYSICAL_MAXIMUM (0)
+    0x85, REPORT_ID, //       REPORT_ID (1)
+    0x09, 0x38, //       USAGE (Wheel)
+    0x15, 0x81, //       LOGICAL_MINIMUM (-127)
+    0x25, 0x7f, //       LOGICAL_MAXIMUM (127)
+    0x75, 0x08, //       REPORT_SIZE (8)
+    0x81, 0x06, //       INPUT (Data,Var,Rel)
+    0xc0, //     END_COLLECTION
+    0xa1, 0x02, //     COLLECTION (Logical)
+    0x85, RES_REPORT_ID, //       REPORT_ID (3)
+    0x09, 0x48, //       USAGE (Resolution Multiplier)
+    0x15, 0x00, //       LOGICAL_MINIMUM (0)
+    0x25, 0x01, //       LOGICAL_MAXIMUM (1)
+    0x35, 0x01, //       PHYSICAL_MINIMUM (1)
+    0x45, HIRES_MULTIPLIER, //       PHYSICAL_MAXIMUM (8)
+    0x75, 0x02, //       REPORT_SIZE (2)
+    0xb1, 0x02, //       FEATURE (Data,Var,Abs)
+    0x35, 0x00, //       PHYSICAL_MINIMUM (0)
+    0x45, 0x00, //       PHYSICAL_MAXIMUM (0)
+    0x85, REPORT_ID, //       REPORT_ID (1)
+    0x05, 0x0c, //       USAGE_PAGE (Consumer Devices)
+    0x0a, 0x38, 0x02, //       USAGE (AC Pan)
+    0x15, 0x81, //       LOGICAL_MINIMUM (-127)
+    0x25, 0x7f, //       LOGICAL_MAXIMUM (127)
+    0x75, 0x08, //       REPORT_SIZE (8)
+    0x81, 0x06, //       INPUT (Data,Var,Rel)
+    0xc0, //     END_COLLECTION
+    0xc0, //   END_COLLECTION
+    0x85, RES_REPORT_ID, //   REPORT_ID (3)
+    0x75, 0x04, //   REPORT_SIZE (4)
+    0xb1, 0x03, //   FEATURE (Cnst,Var,Abs)
+    0xc0, // END_COLLECTION
+    0x06, 0x00, 0xff, // USAGE_PAGE (Vendor Defined 0xFF00)
+    0x09, 0x01, // USAGE (Vendor Usage 1)
+    0xa1, 0x01, // COLLECTION (Application)
+    0x85, config_proto::REPORT_ID, //   REPORT_ID (2)
+    0x09, 0x02, //   USAGE (Vendor Usage 2)
+    0x15, 0x00, //   LOGICAL_MINIMUM (0)
+    0x26, 0xff, 0x00, //   LOGICAL_MAXIMUM (255)
+    0x75, 0x08, //   REPORT_SIZE (8)
+    0x95, config_proto::REPORT_SIZE as u8, //   REPORT_COUNT (32)
+    0xb1, 0x02, //   FEATURE (Data,Var,Abs)
+    0xc0, // END_COLLECTION
+];
+
+/// Range of X/Y in a report
+pub const XY_MAX: i16 = 32767;
+
+/// Report protocol, selected by the host with SET_PROTOCOL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// Boot report, 3 buttons and i8 X/Y
+    Boot = 0,
+    /// Report as of `REPORT_DESCR`, the default after reset
+    Report = 1,
+}
+
+/// The input report, see `REPORT_DESCR`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MouseReport {
+    /// bit 0 left, 1 right, 2 middle, 3 back, 4 forward
+    pub buttons: u8,
+    /// -XY_MAX..=XY_MAX
+    pub x: i16,
+    pub y: i16,
+    pub wheel: i8,
+    pub pan: i8,
+}
+
+impl MouseReport {
+    pub const SIZE: usize = 8;
+
+    /// The report protocol report, prefixed by `REPORT_ID`
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let x = self.x.to_le_bytes();
+        let y = self.y.to_le_bytes();
+        [
+            REPORT_ID,
+            self.buttons & 0x1f,
+            x[0],
+            x[1],
+            y[0],
+            y[1],
+            self.wheel as u8,
+            self.pan as u8,
+        ]
+    }
+
+    pub const BOOT_SIZE: usize = 3;
+
+    /// The boot protocol report, X/Y saturated to the i8 range
+    pub fn to_boot_bytes(&self) -> [u8; Self::BOOT_SIZE] {
+        let sat = |v: i16| v.max(i8::MIN as i16 + 1).min(i8::MAX as i16) as i8 as u8;
+        [self.buttons & 0x07, sat(self.x), sat(self.y)]
+    }
+}
+
+pub struct MouseClass<'a, B: UsbBus> {
+    report_if: InterfaceNumber,
+    report_ep: EndpointIn<'a, B>,
+    protocol: Protocol,
+    // the current button state
+    buttons: u8,
+    // idle rate per report ID, in 4 ms units, 0 for indefinite
+    idle: [u8; REPORT_IDS],
+    // time since the last report, in ms
+    idle_ms: u32,
+    // a report is written, but not yet collected by the host
+    busy: bool,
+    // resolution multipliers, bits 0-1 wheel, 2-3 pan, as set by the host
+    resolution: u8,
+    // configuration request not yet taken, and the latest response
+    config_request: Option<[u8; config_proto::REPORT_SIZE]>,
+    config_response: [u8; config_proto::REPORT_SIZE],
+}
+
+impl<B: UsbBus> MouseClass<'_, B> {
+    /// Creates a new mouse interface, polled every `interval_ms` (bInterval,
+    /// 1 to 255 ms at full speed)
+    pub fn new(alloc: &UsbBusAllocator<B>, interval_ms: u8) -> MouseClass<'_, B> {
+        MouseClass {
+            report_if: alloc.interface(),
+            report_ep: alloc.interrupt(8, interval_ms.max(1)),
+            protocol: Protocol::Report,
+            buttons: 0,
+            idle: [0; REPORT_IDS],
+            idle_ms: 0,
+            busy: false,
+            resolution: 0,
+            config_request: None,
+            config_response: [0; config_proto::REPORT_SIZE],
+        }
+    }
+
+    /// The protocol currently selected by the host
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// The previous report is not yet collected by the host
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Wheel and pan units per detent (1 or `HIRES_MULTIPLIER`), as enabled
+    /// by the host
+    ///
+    /// A host without support never enables it, neither in boot protocol.
+    pub fn resolution_multiplier(&self) -> (u8, u8) {
+        let multiplier = |bits: u8| {
+            if self.protocol == Protocol::Report && bits & 0x03 != 0 {
+                HIRES_MULTIPLIER
+            } else {
+                1
+            }
+        };
+        (multiplier(self.resolution), multiplier(self.resolution >> 2))
+    }
+
+    /// Sends a report in the current protocol, fails with `WouldBlock` if
+    /// the previous is pending
+    ///
+    /// The buttons are taken as the current state, even if not sent.
+    pub fn push(&mut self, report: &MouseReport) -> Result<usize> {
+        self.buttons = report.buttons;
+        if self.busy {
+            return Err(UsbError::WouldBlock);
+        }
+        let n = self.report_ep.write(self.encode(report).as_ref())?;
+        self.busy = true;
+        self.idle_ms = 0;
+        Ok(n)
+    }
+
+    /// Takes the pending configuration request, see `config_proto`
+    pub fn take_config_request(&mut self) -> Option<[u8; config_proto::REPORT_SIZE]> {
+        self.config_request.take()
+    }
+
+    /// Sets the response to the configuration request, read by the host
+    pub fn set_config_response(&mut self, response: &[u8; config_proto::REPORT_SIZE]) {
+        self.config_response = *response;
+    }
+
+    /// Advances the idle timer by `ms`, on expiry the button state is
+    /// re-sent (without motion, as it was already reported), `true` if it
+    /// was
+    pub fn tick(&mut self, ms: u32) -> bool {
+        self.idle_ms = self.idle_ms.saturating_add(ms);
+        let rate = self.idle[REPORT_ID as usize] as u32 * 4;
+        // on WouldBlock retried the next tick
+        rate != 0 && self.idle_ms >= rate && self.push(&self.state()).is_ok()
+    }
+
+    // The current state as a report
+    fn state(&self) -> MouseReport {
+        MouseReport {
+            buttons: self.buttons,
+            ..MouseReport::default()
+        }
+    }
+
+    // A report encoded for the current protocol
+    fn encode(&self, report: &MouseReport) -> Encoded {
+        match self.protocol {
+            Protocol::Boot => Encoded::Boot(report.to_boot_bytes()),
+            Protocol::Report => Encoded::Report(report.to_bytes()),
+        }
+    }
+}
+
+/// The HID descriptor (after bLength and bDescriptorType) for an interface
+/// with the report descriptor `report_descr`
+pub(crate) fn hid_descriptor(report_descr: &[u8]) -> [u8; 7] {
+    let descr_len = report_descr.len() as u16;
+    [
+        0x11,                   // bcdHID (1.11)
+        0x01,                   // bcdHID
+        0x00,                   // bCountryCode
+        0x01,                   // bNumDescriptors
+        DESC_REPORT,            // bDescriptorType
+        descr_len as u8,        // wDescriptorLength
+        (descr_len >> 8) as u8, // wDescriptorLength
+    ]
+}
+
+enum Encoded {
+    Boot([u8; MouseReport::BOOT_SIZE]),
+    Report([u8; MouseReport::SIZE]),
+}
+
+impl AsRef<[u8]> for Encoded {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Encoded::Boot(r) => r,
+            Encoded::Report(r) => r,
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for MouseClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(
+            self.report_if,
+            USB_CLASS_HID,
+            USB_SUBCLASS_BOOT,
+            USB_INTERFACE_MOUSE,
+        )?;
+        writer.write(DESC_HID, &hid_descriptor(REPORT_DESCR))?;
+        writer.endpoint(&self.report_ep)?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.protocol = Protocol::Report;
+        self.idle = [0; REPORT_IDS];
+        self.idle_ms = 0;
+        self.busy = false;
+        self.resolution = 0;
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.report_ep.address() {
+            self.busy = false;
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+
+        if !(req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.report_if) as u16)
+        {
+            return;
+        }
+
+        if req.request_type == control::RequestType::Standard {
+            if req.request == control::Request::GET_DESCRIPTOR {
+                let (dtype, _index) = req.descriptor_type_index();
+                if dtype == DESC_HID {
+                    let mut descr = [0; 9];
+                    descr[0] = 9; // bLength
+                    descr[1] = DESC_HID;
+                    descr[2..].copy_from_slice(&hid_descriptor(REPORT_DESCR));
+                    xfer.accept_with(&descr).ok();
+                } else if dtype == DESC_REPORT {
+                    xfer.accept_with(REPORT_DESCR).ok();
+                }
+            }
+            return;
+        }
+
+        if req.request_type != control::RequestType::Class {
+            return;
+        }
+
+        match req.request {
+            REQ_GET_REPORT => {
+                // wValue, report type (high) and report ID (low)
+                let (rtype, id) = ((req.value >> 8) as u8, (req.value & 0xff) as usize);
+                if rtype == REPORT_TYPE_INPUT && id < REPORT_IDS {
+                    let report = self.encode(&self.state());
+                    xfer.accept_with(report.as_ref()).ok();
+                } else if rtype == REPORT_TYPE_FEATURE && id == RES_REPORT_ID as usize {
+                    xfer.accept_with(&[RES_REPORT_ID, self.resolution]).ok();
+                } else if rtype == REPORT_TYPE_FEATURE && id == config_proto::REPORT_ID as usize {
+                    let mut report = [0; config_proto::REPORT_SIZE + 1];
+                    report[0] = config_proto::REPORT_ID;
+                    report[1..].copy_from_slice(&self.config_response);
+                    xfer.accept_with(&report).ok();
+                } else {
+                    xfer.reject().ok();
+                }
+            }
+            REQ_GET_IDLE => {
+                let id = (req.value & 0xff) as usize;
+                if id < REPORT_IDS {
+                    xfer.accept_with(&[self.idle[id]]).ok();
+                } else {
+                    xfer.reject().ok();
+                }
+            }
+            REQ_GET_PROTOCOL => {
+                xfer.accept_with(&[self.protocol as u8]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.report_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_SET_PROTOCOL => {
+                self.protocol = match req.value {
+                    0 => Protocol::Boot,
+                    1 => Protocol::Report,
+                    _ => {
+                        xfer.reject().ok();
+                        return;
+                    }
+                };
+                xfer.accept().ok();
+            }
+            REQ_SET_REPORT
+                if req.value == (REPORT_TYPE_FEATURE as u16) << 8 | RES_REPORT_ID as u16 =>
+            {
+                // [RES_REPORT_ID, multipliers]
+                match xfer.data() {
+                    [RES_REPORT_ID, resolution, ..] => {
+                        self.resolution = resolution & 0x0f;
+                        xfer.accept().ok();
+                    }
+                    _ => {
+                        xfer.reject().ok();
+                    }
+                }
+            }
+            REQ_SET_REPORT
+                if req.value
+                    == (REPORT_TYPE_FEATURE as u16) << 8 | config_proto::REPORT_ID as u16 =>
+            {
+                // the data is prefixed by the report ID
+                let data = xfer.data();
+                if data.first() != Some(&config_proto::REPORT_ID) {
+                    xfer.reject().ok();
+                    return;
+                }
+                let mut request = [0; config_proto::REPORT_SIZE];
+                let n = (data.len() - 1).min(request.len());
+                request[..n].copy_from_slice(&data[1..=n]);
+                // until handled, the response reads as pending
+                let id = request[1];
+                self.config_response =
+                    config_proto::encode_response(id, &Err(config_proto::Status::Pending));
+                self.config_request = Some(request);
+                xfer.accept().ok();
+            }
+            REQ_SET_IDLE => {
+                // wValue, duration (high) and report ID (low), 0 for all
+                let (rate, id) = ((req.value >> 8) as u8, (req.value & 0xff) as usize);
+                if id == 0 {
+                    self.idle = [rate; REPORT_IDS];
+                } else if id < REPORT_IDS {
+                    self.idle[id] = rate;
+                } else {
+                    xfer.reject().ok();
+                    return;
+                }
+                self.idle_ms = 0;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
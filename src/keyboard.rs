@@ -0,0 +1,242 @@
+//! USB HID keyboard class
+//!
+//! A boot keyboard interface (modifiers + 6 keys), placed next to the mouse
+//! interface to make a composite device. Lets buttons emit key combinations,
+//! e.g., copy/paste or push-to-talk, see `buttons::ButtonMap`.
+//!
+//! The report protocol uses the boot report layout, so SET_PROTOCOL only
+//! needs to be acknowledged. The host LED state (SET_REPORT) is kept.
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+use crate::hid::{
+    hid_descriptor, DESC_HID, DESC_REPORT, REPORT_TYPE_INPUT, REPORT_TYPE_OUTPUT,
+    REQ_GET_IDLE, REQ_GET_PROTOCOL, REQ_GET_REPORT, REQ_SET_IDLE, REQ_SET_PROTOCOL,
+    REQ_SET_REPORT, USB_CLASS_HID, USB_SUBCLASS_BOOT,
+};
+
+mod report;
+pub use report::*;
+
+const USB_INTERFACE_KEYBOARD: u8 = 0x01;
+
+// HID 1.11, Appendix B.1, boot keyboard
+pub const REPORT_DESCR: &[u8] = &[
+    0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+    0x09, 0x06, // USAGE (Keyboard)
+    0xa1, 0x01, // COLLECTION (Application)
+    0x05, 0x07, //   USAGE_PAGE (Keyboard)
+    0x19, 0xe0, //   USAGE_MINIMUM (Left Control)
+    0x29, 0xe7, //   USAGE_MAXIMUM (Right GUI)
+    0x15, 0x00, //   LOGICAL_MINIMUM (0)
+    0x25, 0x01, //   LOGICAL_MAXIMUM (1)
+    0x75, 0x01, //   REPORT_SIZE (1)
+    0x95, 0x08, //   REPORT_COUNT (8)
+    0x81, 0x02, //   INPUT (Data,Var,Abs)
+    0x95, 0x01, //   REPORT_COUNT (1)
+    0x75, 0x08, //   REPORT_SIZE (8)
+    0x81, 0x03, //   INPUT (Cnst,Var,Abs)
+    0x95, 0x05, //   REPORT_COUNT (5)
+    0x75, 0x01, //   REPORT_SIZE (1)
+    0x05, 0x08, //   USAGE_PAGE (LEDs)
+    0x19, 0x01, //   USAGE_MINIMUM (Num Lock)
+    0x29, 0x05, //   USAGE_MAXIMUM (Kana)
+    0x91, 0x02, //   OUTPUT (Data,Var,Abs)
+    0x95, 0x01, //   REPORT_COUNT (1)
+    0x75, 0x03, //   REPORT_SIZE (3)
+    0x91, 0x03, //   OUTPUT (Cnst,Var,Abs)
+    0x95, 0x06, //   REPORT_COUNT (6)
+    0x75, 0x08, //   REPORT_SIZE (8)
+    0x15, 0x00, //   LOGICAL_MINIMUM (0)
+    0x25, KEY_MAX, //   LOGICAL_MAXIMUM (101)
+    0x05, 0x07, //   USAGE_PAGE (Keyboard)
+    0x19, 0x00, //   USAGE_MINIMUM (Reserved)
+    0x29, KEY_MAX, //   USAGE_MAXIMUM (Keyboard Application)
+    0x81, 0x00, //   INPUT (Data,Ary,Abs)
+    0xc0, // END_COLLECTION
+];
+
+pub struct KeyboardClass<'a, B: UsbBus> {
+    report_if: InterfaceNumber,
+    report_ep: EndpointIn<'a, B>,
+    // the current key state, and if not yet sent
+    report: KeyboardReport,
+    pending: bool,
+    // a report is written, but not yet collected by the host
+    busy: bool,
+    // idle rate in 4 ms units, 0 for indefinite
+    idle: u8,
+    // time since the last report, in ms
+    idle_ms: u32,
+    protocol: u8,
+    leds: u8,
+}
+
+impl<B: UsbBus> KeyboardClass<'_, B> {
+    /// Creates a new keyboard interface, polled every 10 ms
+    pub fn new(alloc: &UsbBusAllocator<B>) -> KeyboardClass<'_, B> {
+        KeyboardClass {
+            report_if: alloc.interface(),
+            report_ep: alloc.interrupt(8, 10),
+            report: KeyboardReport::default(),
+            pending: false,
+            busy: false,
+            // 500 ms, the recommended default for keyboards
+            idle: 125,
+            idle_ms: 0,
+            protocol: 1,
+            leds: 0,
+        }
+    }
+
+    /// The LED state set by the host, bit 0 Num Lock, 1 Caps Lock, 2 Scroll Lock
+    pub fn leds(&self) -> u8 {
+        self.leds
+    }
+
+    /// Sets the key state, sent by `flush` if changed
+    pub fn set_report(&mut self, report: &KeyboardReport) {
+        if *report != self.report {
+            self.report = *report;
+            self.pending = true;
+        }
+    }
+
+    /// Sends a changed key state if the endpoint is free, `Ok(true)` if sent
+    pub fn flush(&mut self) -> Result<bool> {
+        if !self.pending {
+            return Ok(false);
+        }
+        self.send()?;
+        Ok(true)
+    }
+
+    /// Advances the idle timer by `ms`, on expiry the key state is re-sent
+    pub fn tick(&mut self, ms: u32) {
+        self.idle_ms = self.idle_ms.saturating_add(ms);
+        let rate = self.idle as u32 * 4;
+        if rate != 0 && self.idle_ms >= rate {
+            // on WouldBlock retried the next tick
+            self.send().ok();
+        }
+    }
+
+    fn send(&mut self) -> Result<usize> {
+        if self.busy {
+            return Err(UsbError::WouldBlock);
+        }
+        let n = self.report_ep.write(&self.report.to_bytes())?;
+        self.busy = true;
+        self.pending = false;
+        self.idle_ms = 0;
+        Ok(n)
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for KeyboardClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(
+            self.report_if,
+            USB_CLASS_HID,
+            USB_SUBCLASS_BOOT,
+            USB_INTERFACE_KEYBOARD,
+        )?;
+        writer.write(DESC_HID, &hid_descriptor(REPORT_DESCR))?;
+        writer.endpoint(&self.report_ep)?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.pending = self.report != KeyboardReport::default();
+        self.busy = false;
+        self.idle = 125;
+        self.idle_ms = 0;
+        self.protocol = 1;
+        self.leds = 0;
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.report_ep.address() {
+            self.busy = false;
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = xfer.request();
+
+        if !(req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.report_if) as u16)
+        {
+            return;
+        }
+
+        if req.request_type == control::RequestType::Standard {
+            if req.request == control::Request::GET_DESCRIPTOR {
+                let (dtype, _index) = req.descriptor_type_index();
+                if dtype == DESC_HID {
+                    let mut descr = [0; 9];
+                    descr[0] = 9; // bLength
+                    descr[1] = DESC_HID;
+                    descr[2..].copy_from_slice(&hid_descriptor(REPORT_DESCR));
+                    xfer.accept_with(&descr).ok();
+                } else if dtype == DESC_REPORT {
+                    xfer.accept_with(REPORT_DESCR).ok();
+                }
+            }
+            return;
+        }
+
+        if req.request_type != control::RequestType::Class {
+            return;
+        }
+
+        match req.request {
+            REQ_GET_REPORT if (req.value >> 8) as u8 == REPORT_TYPE_INPUT => {
+                xfer.accept_with(&self.report.to_bytes()).ok();
+            }
+            REQ_GET_IDLE => {
+                xfer.accept_with(&[self.idle]).ok();
+            }
+            REQ_GET_PROTOCOL => {
+                xfer.accept_with(&[self.protocol]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+
+        if !(req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.report_if) as u16)
+        {
+            return;
+        }
+
+        match req.request {
+            REQ_SET_REPORT if (req.value >> 8) as u8 == REPORT_TYPE_OUTPUT => {
+                self.leds = xfer.data().first().copied().unwrap_or(0);
+                xfer.accept().ok();
+            }
+            REQ_SET_IDLE => {
+                // wValue, duration (high), a single report so the ID is ignored
+                self.idle = (req.value >> 8) as u8;
+                self.idle_ms = 0;
+                xfer.accept().ok();
+            }
+            REQ_SET_PROTOCOL if req.value <= 1 => {
+                // same report layout in both protocols
+                self.protocol = req.value as u8;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
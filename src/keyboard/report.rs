@@ -0,0 +1,44 @@
+//! Boot keyboard input report and modifier bits
+//!
+//! Target independent, shared with the host tools.
+
+/// Modifier bits
+pub const MOD_LCTRL: u8 = 0x01;
+pub const MOD_LSHIFT: u8 = 0x02;
+pub const MOD_LALT: u8 = 0x04;
+pub const MOD_LGUI: u8 = 0x08;
+pub const MOD_RCTRL: u8 = 0x10;
+pub const MOD_RSHIFT: u8 = 0x20;
+pub const MOD_RALT: u8 = 0x40;
+pub const MOD_RGUI: u8 = 0x80;
+
+/// Highest key usage ID in the report (Keyboard Application)
+pub const KEY_MAX: u8 = 0x65;
+
+/// The input report, modifiers and up to 6 pressed keys (usage IDs)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeyboardReport {
+    pub modifiers: u8,
+    pub keys: [u8; 6],
+}
+
+impl KeyboardReport {
+    pub const SIZE: usize = 8;
+
+    /// Adds a pressed key, ignored if already pressed or 6 keys are pressed
+    pub fn press(&mut self, key: u8) {
+        if key == 0 || self.keys.contains(&key) {
+            return;
+        }
+        if let Some(k) = self.keys.iter_mut().find(|k| **k == 0) {
+            *k = key;
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = self.modifiers;
+        bytes[2..].copy_from_slice(&self.keys);
+        bytes
+    }
+}
@@ -1,7 +1,26 @@
 #![no_std]
 
+pub mod buttons;
+pub mod cdc_acm;
+pub mod config_proto;
+pub mod console;
+pub mod dfu;
+pub mod encoder;
+pub mod flash;
+pub mod hid;
+pub mod keyboard;
+pub mod low_power;
+pub mod odometry;
 pub mod pmw3389;
 pub mod pmw3389e;
+pub mod report_queue;
+pub mod report_stats;
+pub mod sc18is602;
+pub mod scaler;
+pub mod scroll;
+pub mod suspend;
+pub mod usb_id;
+pub mod velocity;
 
 use stm32f4xx_hal::{prelude::*, rcc::Clocks, stm32};
 
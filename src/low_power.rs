@@ -0,0 +1,150 @@
+//! Stop mode while the USB is suspended
+//!
+//! In Stop mode all clocks (the PLL, HSI and HSE) are off, also the cycle
+//! counter, so RTIC schedules no tasks. RAM and registers are retained,
+//! the regulator and the flash are in their low power modes (LPDS, FPDS)
+//! and the USB PHY clock is gated (STPPCLK).
+//!
+//! The MCU is woken by:
+//!
+//! - the RTC wakeup timer (`WakeupTimer`, EXTI line 22, `RTC_WKUP`),
+//!   clocked by the LSI, for sampling the sensor and buttons,
+//! - the USB wakeup event (EXTI line 18, `OTG_FS_WKUP`), on a resume or
+//!   reset by the host.
+//!
+//! The system clock (the PLL) and the PHY clock are restored before the
+//! interrupt is served. A debugger (and RTT) loses the connection while
+//! stopped.
+
+use cortex_m::{asm::wfi, peripheral::SCB};
+use stm32f4xx_hal::stm32::{EXTI, OTG_FS_PWRCLK, PWR, RCC, RTC};
+
+// EXTI lines of the USB OTG FS and the RTC wakeup events
+const EXTI_OTG_FS_WKUP: u32 = 1 << 18;
+const EXTI_RTC_WKUP: u32 = 1 << 22;
+
+// OTG_FS_PCGCCTL, stop the PHY clock
+const PCGCCTL_STPPCLK: u32 = 1 << 0;
+
+// LSI (nominally 32 kHz, 17 to 47 kHz) divided by 16 (WUCKSEL = 0b000)
+const WAKEUP_HZ: u32 = 32_000 / 16;
+
+/// Enters Stop mode, from `idle`
+pub struct StopMode {
+    scb: SCB,
+}
+
+impl StopMode {
+    /// Sets up Stop mode and the USB wakeup event (EXTI line 18)
+    pub fn new(scb: SCB, pwr: &PWR, exti: &EXTI) -> Self {
+        unsafe { (*RCC::ptr()).apb1enr.modify(|_, w| w.pwren().set_bit()) };
+        // Stop (not Standby), low power regulator and flash
+        pwr.cr
+            .modify(|_, w| w.pdds().clear_bit().lpds().set_bit().fpds().set_bit());
+        enable_exti(exti, EXTI_OTG_FS_WKUP);
+        StopMode { scb }
+    }
+
+    /// Stops until an interrupt is pending, then restores the clocks
+    ///
+    /// To be called with interrupts masked (`interrupt::free`), the
+    /// interrupt waking the MCU is then served on the restored clocks.
+    pub fn enter(&mut self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        let pcgcctl = unsafe { &(*OTG_FS_PWRCLK::ptr()).fs_pcgcctl };
+        let hse = rcc.cr.read().hseon().bit_is_set();
+
+        pcgcctl.modify(|r, w| unsafe { w.bits(r.bits() | PCGCCTL_STPPCLK) });
+        self.scb.set_sleepdeep();
+        wfi();
+        self.scb.clear_sleepdeep();
+
+        // woken on the HSI, the PLL (and the HSE feeding it) are off
+        if hse {
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+            while rcc.cr.read().hserdy().bit_is_clear() {}
+        }
+        rcc.cr.modify(|_, w| w.pllon().set_bit());
+        while rcc.cr.read().pllrdy().bit_is_clear() {}
+        rcc.cfgr.modify(|_, w| w.sw().pll());
+        while !rcc.cfgr.read().sws().is_pll() {}
+        pcgcctl.modify(|r, w| unsafe { w.bits(r.bits() & !PCGCCTL_STPPCLK) });
+    }
+}
+
+/// Clears the USB wakeup event, in the `OTG_FS_WKUP` handler
+pub fn clear_usb_wakeup() {
+    unsafe { (*EXTI::ptr()).pr.write(|w| w.bits(EXTI_OTG_FS_WKUP)) };
+}
+
+/// The RTC wakeup timer, periodically waking the MCU from Stop mode
+pub struct WakeupTimer {
+    rtc: RTC,
+}
+
+impl WakeupTimer {
+    /// Sets up the RTC, clocked by the LSI, and its wakeup event (EXTI
+    /// line 22), the timer is disabled until `start`
+    pub fn new(rtc: RTC, pwr: &PWR, exti: &EXTI) -> Self {
+        let rcc = unsafe { &*RCC::ptr() };
+        // write access to the backup domain (DBP), where the RTC is
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        pwr.cr.modify(|_, w| w.dbp().set_bit());
+        rcc.csr.modify(|_, w| w.lsion().set_bit());
+        while rcc.csr.read().lsirdy().bit_is_clear() {}
+        // RTCSEL is kept over a reset, only set once (LSI)
+        rcc.bdcr
+            .modify(|_, w| unsafe { w.rtcsel().bits(0b10) }.rtcen().set_bit());
+
+        let mut timer = WakeupTimer { rtc };
+        timer.disable();
+        enable_exti(exti, EXTI_RTC_WKUP);
+        timer
+    }
+
+    /// Starts waking up every `ms` (1 to 32000)
+    pub fn start(&mut self, ms: u32) {
+        let wut = (ms * WAKEUP_HZ / 1000).max(1) - 1;
+        self.write(|rtc| {
+            rtc.wutr.write(|w| unsafe { w.wut().bits(wut as u16) });
+            rtc.cr.modify(|_, w| {
+                unsafe { w.wucksel().bits(0b000) }
+                    .wutie()
+                    .set_bit()
+                    .wute()
+                    .set_bit()
+            });
+        });
+    }
+
+    /// Stops waking up, a pending wakeup is cleared
+    pub fn disable(&mut self) {
+        self.write(|_| {});
+        self.clear();
+    }
+
+    /// Clears the wakeup event, in the `RTC_WKUP` handler
+    pub fn clear(&mut self) {
+        self.rtc.isr.modify(|_, w| w.wutf().clear_bit());
+        unsafe { (*EXTI::ptr()).pr.write(|w| w.bits(EXTI_RTC_WKUP)) };
+    }
+
+    // Writes the wakeup timer settings with the timer disabled, the
+    // registers unprotected meanwhile
+    fn write<F: FnOnce(&RTC)>(&mut self, f: F) {
+        let rtc = &self.rtc;
+        rtc.wpr.write(|w| unsafe { w.key().bits(0xca) });
+        rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        rtc.cr
+            .modify(|_, w| w.wutie().clear_bit().wute().clear_bit());
+        while rtc.isr.read().wutwf().bit_is_clear() {}
+        f(rtc);
+        rtc.wpr.write(|w| unsafe { w.key().bits(0xff) });
+    }
+}
+
+// Interrupt on the rising edge of an EXTI line
+fn enable_exti(exti: &EXTI, line: u32) {
+    exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+    exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+}
@@ -0,0 +1,141 @@
+//! Two-sensor odometry
+//!
+//! Two sensors mounted at known positions on a rigid body measure the
+//! same motion, but differ by the rotation of the body. From their
+//! (synchronized) motion we track the position in mm and the heading in
+//! radians of the body's reference point.
+//!
+//! Target independent, so it can be exercised on the host.
+
+use core::f32::consts::PI;
+use libm::{cosf, fmodf, sinf};
+
+const MM_PER_INCH: f32 = 25.4;
+
+/// Mounting of a sensor relative to the reference point of the body
+///
+/// Body coordinates, x forward, y left, angles counter clockwise.
+#[derive(Clone, Copy, Debug)]
+pub struct Mount {
+    /// position in mm
+    pub x: f32,
+    pub y: f32,
+    /// rotation of the sensor x-axis relative to the body x-axis, in radians
+    pub angle: f32,
+    /// resolution in counts per inch
+    pub cpi: u16,
+}
+
+// A mount, with the count to (rotated) mm conversion precomputed
+#[derive(Clone, Copy, Debug)]
+struct Sensor {
+    x: f32,
+    y: f32,
+    cos: f32,
+    sin: f32,
+    mm_per_count: f32,
+}
+
+impl Sensor {
+    fn new(mount: Mount) -> Self {
+        Sensor {
+            x: mount.x,
+            y: mount.y,
+            cos: cosf(mount.angle),
+            sin: sinf(mount.angle),
+            mm_per_count: MM_PER_INCH / mount.cpi as f32,
+        }
+    }
+
+    // displacement in body coordinates, in mm
+    fn displacement(&self, (dx, dy): (i16, i16)) -> (f32, f32) {
+        let dx = dx as f32 * self.mm_per_count;
+        let dy = dy as f32 * self.mm_per_count;
+        (dx * self.cos - dy * self.sin, dx * self.sin + dy * self.cos)
+    }
+}
+
+pub struct Odometry {
+    sensors: [Sensor; 2],
+    // 1 / |baseline|^2, baseline being the vector from sensor 0 to sensor 1
+    inv_baseline_sq: f32,
+    x: f32,
+    y: f32,
+    heading: f32,
+}
+
+impl Odometry {
+    /// Creates odometry for two sensors, starting at the origin, heading 0
+    ///
+    /// Panics if the sensors are mounted at the same position, or a
+    /// resolution is 0.
+    pub fn new(a: Mount, b: Mount) -> Self {
+        assert!(a.cpi > 0 && b.cpi > 0, "sensor resolution must be non-zero");
+        let (bx, by) = (b.x - a.x, b.y - a.y);
+        let baseline_sq = bx * bx + by * by;
+        assert!(baseline_sq > 0.0, "sensors must be mounted apart");
+
+        Odometry {
+            sensors: [Sensor::new(a), Sensor::new(b)],
+            inv_baseline_sq: 1.0 / baseline_sq,
+            x: 0.0,
+            y: 0.0,
+            heading: 0.0,
+        }
+    }
+
+    /// Integrates one pair of synchronized motion reports (dx, dy) in counts
+    pub fn update(&mut self, a: (i16, i16), b: (i16, i16)) {
+        let [sa, sb] = self.sensors;
+        let (ax, ay) = sa.displacement(a);
+        let (bx, by) = sb.displacement(b);
+
+        // A rotation dθ moves a point r by dθ * (-r.y, r.x), so the
+        // difference between the sensors is dθ * perp(baseline)
+        let (lx, ly) = (sb.x - sa.x, sb.y - sa.y);
+        let d_heading = ((bx - ax) * -ly + (by - ay) * lx) * self.inv_baseline_sq;
+
+        // translation of the reference point, averaged over both sensors
+        let tx = (ax + d_heading * sa.y + bx + d_heading * sb.y) * 0.5;
+        let ty = (ay - d_heading * sa.x + by - d_heading * sb.x) * 0.5;
+
+        // rotate into world coordinates, using the mid-point heading
+        let mid = self.heading + d_heading * 0.5;
+        let (cos, sin) = (cosf(mid), sinf(mid));
+        self.x += tx * cos - ty * sin;
+        self.y += tx * sin + ty * cos;
+        self.heading = wrap(self.heading + d_heading);
+    }
+
+    /// Position (x, y) in mm
+    pub fn position(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    /// Heading in radians, in the range [-PI, PI)
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+
+    /// Sets the current pose
+    pub fn set_pose(&mut self, x: f32, y: f32, heading: f32) {
+        self.x = x;
+        self.y = y;
+        self.heading = wrap(heading);
+    }
+}
+
+// Wraps into [-PI, PI), NaN if not finite
+fn wrap(angle: f32) -> f32 {
+    let mut wrapped = fmodf(angle + PI, 2.0 * PI);
+    if wrapped < 0.0 {
+        wrapped += 2.0 * PI;
+    }
+    wrapped -= PI;
+    // rounding may land on the (excluded) upper bound
+    if wrapped >= PI {
+        -PI
+    } else {
+        wrapped
+    }
+}
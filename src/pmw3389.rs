@@ -8,77 +8,34 @@ use embedded_hal::digital::v2::OutputPin;
 
 use rtt_target::{rprint, rprintln};
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
-pub enum Register {
-    ProductId = 0x00,
-    RevisionId = 0x01,
-    Motion = 0x02,
-    DeltaXL = 0x03,
-    DeltaXH = 0x04,
-    DeltaYL = 0x05,
-    DeltaYH = 0x06,
-    SQUAL = 0x07,
-    RawDataSum = 0x08,
-    MaximumRawdata = 0x09,
-    MinimumRawdata = 0x0A,
-    ShutterLower = 0x0B,
-    ShutterUpper = 0x0C,
-    RippleControl = 0x0D,
-    ResolutionL = 0x0E,
-    ResolutionH = 0x0F,
-    Config2 = 0x10,
-    AngleTune = 0x11,
-    FrameCapture = 0x12,
-    SROMEnable = 0x13,
-    RunDownshift = 0x14,
-    Rest1RateLower = 0x15,
-    Rest1RateUpper = 0x16,
-    Rest1Downshift = 0x17,
-    Rest2RateLower = 0x18,
-    Rest2RateUpper = 0x19,
-    Rest2Downshift = 0x1A,
-    Rest3RateLower = 0x1B,
-    Rest3RateUpper = 0x1C,
-    Observation = 0x24,
-    DataOutLower = 0x25,
-    DataOutUpper = 0x26,
-    RawDataDump = 0x29,
-    SROMId = 0x2A,
-    MinSQRun = 0x2B,
-    RawDataThreshold = 0x2C,
-    Control2 = 0x2D,
-    Config5L = 0x2E,
-    Config5H = 0x2F,
-    PowerUpReset = 0x3A,
-    Shutdown = 0x3B,
-    InverseProductID = 0x3F,
-    LiftCutoffTune3 = 0x41,
-    AngleSnap = 0x42,
-    LiftCutoffTune1 = 0x4A,
-    MotionBurst = 0x50,
-    LiftCutoffTune1Timeout = 0x58,
-    LiftCutoffTune1MinLength = 0x5A,
-    SROMLoadBurst = 0x62,
-    LiftConfig = 0x63,
-    RawDataBurst = 0x64,
-    LiftCutoffTune2 = 0x65,
-    LiftCutoffTune2Timeout = 0x71,
-    LiftCutoffTune2MinLength = 0x72,
-    PWMPeriodCnt = 0x73,
-    PWMWidthCnt = 0x74,
-}
-
-impl Register {
-    fn addr(self) -> u8 {
-        self as u8
-    }
+mod register;
+pub use register::Register;
+
+// ProductId and InverseProductID of a present sensor
+const PRODUCT_ID: u8 = 0x47;
+const INVERSE_PRODUCT_ID: u8 = 0xb8;
+
+// Reconnection backoff, in ms
+const BACKOFF_MIN: u32 = 1;
+const BACKOFF_MAX: u32 = 1024;
+
+/// Connection status of the sensor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Connected,
+    Disconnected,
 }
 
 pub struct Pmw3389<SPI, CS> {
     spi: SPI,
     cs: CS,
     delay: DwtDelay,
+    status: Status,
+    // time to wait before the next reconnection attempt
+    backoff: u32,
+    // of the latest motion burst
+    squal: u8,
+    shutter: u16,
 }
 
 impl<SPI, CS, E> Pmw3389<SPI, CS>
@@ -96,7 +53,15 @@ where
 
     /// Creates a new driver from a SPI peripheral and a NCS pin
     pub fn new(spi: SPI, cs: CS, delay: DwtDelay) -> Result<Self, E> {
-        let mut pmw3389 = Pmw3389 { spi, cs, delay };
+        let mut pmw3389 = Pmw3389 {
+            spi,
+            cs,
+            delay,
+            status: Status::Disconnected,
+            backoff: BACKOFF_MIN,
+            squal: 0,
+            shutter: 0,
+        };
 
         rprintln!("pmw3389 - new");
 
@@ -133,27 +98,9 @@ where
         // pmw3389.com_end();
         // pmw3389.delay.delay_us(40);
 
-        // force reset
-        pmw3389.write_register(Register::PowerUpReset, 0x5a)?;
-
-        // wait for reboot
-        pmw3389.delay.delay_ms(50);
-
-        // read product id
-        let id = pmw3389.product_id()?;
-        rprintln!("product_id 0x{:x}", id);
-
-        let srom_id = pmw3389.read_register(Register::SROMId)?;
-        rprintln!("srom_id {}, 0x{:x}", srom_id, srom_id);
-
-        // read registers 0x02 to 0x06 (and discard the data)
-        pmw3389.read_register(Register::Motion)?;
-        pmw3389.read_register(Register::DeltaXL)?;
-        pmw3389.read_register(Register::DeltaXH)?;
-        pmw3389.read_register(Register::DeltaYL)?;
-        pmw3389.read_register(Register::DeltaYH)?;
-
-        pmw3389.upload_firmware()?;
+        if pmw3389.init()? == Status::Disconnected {
+            rprintln!("pmw3389 - not connected");
+        }
 
         pmw3389.delay.delay_ms(1000);
 
@@ -201,6 +148,39 @@ where
         Ok(pmw3389)
     }
 
+    // Resets the sensor and uploads the firmware, if the sensor is present
+    fn init(&mut self) -> Result<Status, E> {
+        // force reset
+        self.write_register(Register::PowerUpReset, 0x5a)?;
+
+        // wait for reboot
+        self.delay.delay_ms(50);
+
+        // read product id
+        let id = self.product_id()?;
+        rprintln!("product_id 0x{:x}", id);
+
+        let srom_id = self.read_register(Register::SROMId)?;
+        rprintln!("srom_id {}, 0x{:x}", srom_id, srom_id);
+
+        if !self.is_present()? {
+            self.status = Status::Disconnected;
+            return Ok(self.status);
+        }
+
+        // read registers 0x02 to 0x06 (and discard the data)
+        self.read_register(Register::Motion)?;
+        self.read_register(Register::DeltaXL)?;
+        self.read_register(Register::DeltaXH)?;
+        self.read_register(Register::DeltaYL)?;
+        self.read_register(Register::DeltaYH)?;
+
+        self.upload_firmware()?;
+
+        self.status = Status::Connected;
+        Ok(self.status)
+    }
+
     pub fn read_register(&mut self, reg: Register) -> Result<u8, E> {
         self.com_begin();
 
@@ -253,8 +233,103 @@ where
         self.read_register(Register::ProductId)
     }
 
-    /// Read status
-    pub fn read_status(&mut self) -> Result<(), E> {
+    /// Checks the ProductId against the InverseProductID
+    ///
+    /// A loose or missing sensor reads back as all zeros or all ones.
+    pub fn is_present(&mut self) -> Result<bool, E> {
+        let id = self.product_id()?;
+        let inverse_id = self.read_register(Register::InverseProductID)?;
+        Ok(id == PRODUCT_ID && inverse_id == INVERSE_PRODUCT_ID)
+    }
+
+    /// Sets the resolution, 50 to 16000 CPI in steps of 50
+    pub fn set_cpi(&mut self, cpi: u16) -> Result<(), E> {
+        // Resolution_H/L, (n + 1) * 50 CPI
+        let res = cpi.max(50).min(16000) / 50 - 1;
+        self.write_register(Register::ResolutionL, res as u8)?;
+        self.write_register(Register::ResolutionH, (res >> 8) as u8)
+    }
+
+    /// Reads the resolution, in CPI
+    pub fn cpi(&mut self) -> Result<u16, E> {
+        let res = self.read_register(Register::ResolutionL)? as u16
+            | (self.read_register(Register::ResolutionH)? as u16) << 8;
+        Ok((res + 1) * 50)
+    }
+
+    /// Surface quality (SQUAL) of the latest `read_status`
+    pub fn squal(&self) -> u8 {
+        self.squal
+    }
+
+    /// Shutter of the latest `read_status`
+    pub fn shutter(&self) -> u16 {
+        self.shutter
+    }
+
+    /// Shuts the sensor down, until `power_up`
+    pub fn shutdown(&mut self) -> Result<(), E> {
+        self.write_register(Register::Shutdown, 0xb6)
+    }
+
+    /// Powers up the sensor after `shutdown`, resetting it and uploading
+    /// the firmware, the resolution and other settings are to be restored
+    pub fn power_up(&mut self) -> Result<Status, E> {
+        // drop and raise NCS to reset the SPI port
+        self.com_begin();
+        self.delay.delay_us(40);
+        self.com_end();
+        self.delay.delay_us(40);
+        self.init()
+    }
+
+    /// Runs the SROM CRC test, 0xbeef for a correctly loaded firmware
+    ///
+    /// Motion is not tracked during the test (about 10 ms).
+    pub fn srom_crc(&mut self) -> Result<u16, E> {
+        self.write_register(Register::SROMEnable, 0x15)?;
+        self.delay.delay_ms(10);
+        let crc = (self.read_register(Register::DataOutUpper)? as u16) << 8
+            | self.read_register(Register::DataOutLower)? as u16;
+        Ok(crc)
+    }
+
+    /// Connection status, as seen by the latest `read_status`
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Attempts to re-initialize a disconnected sensor, returns `Connected`
+    /// if it is back, then the resolution and other settings are to be
+    /// restored
+    ///
+    /// Takes as long as `new` (a reset and the firmware upload, about
+    /// 100 ms), so better not called from a time critical context.
+    pub fn reconnect(&mut self) -> Result<Status, E> {
+        if self.is_present()? && self.init()? == Status::Connected {
+            rprintln!("pmw3389 - reconnected");
+            self.backoff = BACKOFF_MIN;
+        } else {
+            self.backoff = (self.backoff * 2).min(BACKOFF_MAX);
+        }
+        Ok(self.status)
+    }
+
+    /// Time to wait before the next `reconnect`, in ms, doubled by each
+    /// failed attempt
+    pub fn backoff(&self) -> u32 {
+        self.backoff
+    }
+
+    /// Read status, returns the motion (x, y) since last read
+    ///
+    /// If the sensor is found disconnected, (0, 0) is returned, and
+    /// `status` reports `Disconnected` until it is back by `reconnect`.
+    pub fn read_status(&mut self) -> Result<(i16, i16), E> {
+        if self.status == Status::Disconnected {
+            return Ok((0, 0));
+        }
+
         self.com_begin();
 
         // self.write_register(Register::Motion, 0x01)?;
@@ -279,6 +354,15 @@ where
         }
         rprintln!("]");
 
+        if (buf.iter().all(|b| *b == 0x00) || buf.iter().all(|b| *b == 0xff))
+            && !self.is_present()?
+        {
+            rprintln!("pmw3389 - disconnected");
+            self.status = Status::Disconnected;
+            self.backoff = BACKOFF_MIN;
+            return Ok((0, 0));
+        }
+
         //     SPI.endTransaction(); // Per:Not sure what it does
         //     /*
         //     BYTE[00] = Motion    = if the 7th bit is 1, a motion is detected.
@@ -303,10 +387,12 @@ where
         let surface = buf[0] & 0x08;
         // 0 if on surface / 1 if off surface
 
-        let x = buf[2] as u16 + (buf[3] as u16) << 8;
-        let y = buf[4] as u16 + (buf[5] as u16) << 8;
+        let x = (buf[2] as u16 | (buf[3] as u16) << 8) as i16;
+        let y = (buf[4] as u16 | (buf[5] as u16) << 8) as i16;
 
         let squal = buf[6];
+        self.squal = squal;
+        self.shutter = (buf[10] as u16) << 8 | buf[11] as u16;
 
         rprintln!(
             "motion {}, surface {}, (x, y) {:?}, squal {}",
@@ -316,7 +402,7 @@ where
             squal
         );
 
-        Ok(())
+        Ok((x, y))
     }
 
     // Upload the firmware
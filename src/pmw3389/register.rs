@@ -0,0 +1,135 @@
+//! PMW3389 register map
+//!
+//! Kept free of target dependencies, so that host side tools
+//! (e.g., `host/src/bin/spi_decode.rs`) can share the same table.
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    ProductId = 0x00,
+    RevisionId = 0x01,
+    Motion = 0x02,
+    DeltaXL = 0x03,
+    DeltaXH = 0x04,
+    DeltaYL = 0x05,
+    DeltaYH = 0x06,
+    SQUAL = 0x07,
+    RawDataSum = 0x08,
+    MaximumRawdata = 0x09,
+    MinimumRawdata = 0x0A,
+    ShutterLower = 0x0B,
+    ShutterUpper = 0x0C,
+    RippleControl = 0x0D,
+    ResolutionL = 0x0E,
+    ResolutionH = 0x0F,
+    Config2 = 0x10,
+    AngleTune = 0x11,
+    FrameCapture = 0x12,
+    SROMEnable = 0x13,
+    RunDownshift = 0x14,
+    Rest1RateLower = 0x15,
+    Rest1RateUpper = 0x16,
+    Rest1Downshift = 0x17,
+    Rest2RateLower = 0x18,
+    Rest2RateUpper = 0x19,
+    Rest2Downshift = 0x1A,
+    Rest3RateLower = 0x1B,
+    Rest3RateUpper = 0x1C,
+    Observation = 0x24,
+    DataOutLower = 0x25,
+    DataOutUpper = 0x26,
+    RawDataDump = 0x29,
+    SROMId = 0x2A,
+    MinSQRun = 0x2B,
+    RawDataThreshold = 0x2C,
+    Control2 = 0x2D,
+    Config5L = 0x2E,
+    Config5H = 0x2F,
+    PowerUpReset = 0x3A,
+    Shutdown = 0x3B,
+    InverseProductID = 0x3F,
+    LiftCutoffTune3 = 0x41,
+    AngleSnap = 0x42,
+    LiftCutoffTune1 = 0x4A,
+    MotionBurst = 0x50,
+    LiftCutoffTune1Timeout = 0x58,
+    LiftCutoffTune1MinLength = 0x5A,
+    SROMLoadBurst = 0x62,
+    LiftConfig = 0x63,
+    RawDataBurst = 0x64,
+    LiftCutoffTune2 = 0x65,
+    LiftCutoffTune2Timeout = 0x71,
+    LiftCutoffTune2MinLength = 0x72,
+    PWMPeriodCnt = 0x73,
+    PWMWidthCnt = 0x74,
+}
+
+impl Register {
+    pub fn addr(self) -> u8 {
+        self as u8
+    }
+
+    /// Looks up the register at `addr` (with the read/write bit masked off)
+    pub fn from_addr(addr: u8) -> Option<Register> {
+        use Register::*;
+        match addr & 0x7f {
+            0x00 => Some(ProductId),
+            0x01 => Some(RevisionId),
+            0x02 => Some(Motion),
+            0x03 => Some(DeltaXL),
+            0x04 => Some(DeltaXH),
+            0x05 => Some(DeltaYL),
+            0x06 => Some(DeltaYH),
+            0x07 => Some(SQUAL),
+            0x08 => Some(RawDataSum),
+            0x09 => Some(MaximumRawdata),
+            0x0A => Some(MinimumRawdata),
+            0x0B => Some(ShutterLower),
+            0x0C => Some(ShutterUpper),
+            0x0D => Some(RippleControl),
+            0x0E => Some(ResolutionL),
+            0x0F => Some(ResolutionH),
+            0x10 => Some(Config2),
+            0x11 => Some(AngleTune),
+            0x12 => Some(FrameCapture),
+            0x13 => Some(SROMEnable),
+            0x14 => Some(RunDownshift),
+            0x15 => Some(Rest1RateLower),
+            0x16 => Some(Rest1RateUpper),
+            0x17 => Some(Rest1Downshift),
+            0x18 => Some(Rest2RateLower),
+            0x19 => Some(Rest2RateUpper),
+            0x1A => Some(Rest2Downshift),
+            0x1B => Some(Rest3RateLower),
+            0x1C => Some(Rest3RateUpper),
+            0x24 => Some(Observation),
+            0x25 => Some(DataOutLower),
+            0x26 => Some(DataOutUpper),
+            0x29 => Some(RawDataDump),
+            0x2A => Some(SROMId),
+            0x2B => Some(MinSQRun),
+            0x2C => Some(RawDataThreshold),
+            0x2D => Some(Control2),
+            0x2E => Some(Config5L),
+            0x2F => Some(Config5H),
+            0x3A => Some(PowerUpReset),
+            0x3B => Some(Shutdown),
+            0x3F => Some(InverseProductID),
+            0x41 => Some(LiftCutoffTune3),
+            0x42 => Some(AngleSnap),
+            0x4A => Some(LiftCutoffTune1),
+            0x50 => Some(MotionBurst),
+            0x58 => Some(LiftCutoffTune1Timeout),
+            0x5A => Some(LiftCutoffTune1MinLength),
+            0x62 => Some(SROMLoadBurst),
+            0x63 => Some(LiftConfig),
+            0x64 => Some(RawDataBurst),
+            0x65 => Some(LiftCutoffTune2),
+            0x71 => Some(LiftCutoffTune2Timeout),
+            0x72 => Some(LiftCutoffTune2MinLength),
+            0x73 => Some(PWMPeriodCnt),
+            0x74 => Some(PWMWidthCnt),
+            _ => None,
+        }
+    }
+}
@@ -1,7 +1,5 @@
 /// PWM3389 gaming mouse sensor driver
-use crate::DwtDelay;
-use stm32f4xx_hal::prelude::*;
-
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 use embedded_hal::blocking::spi::Transfer;
 use embedded_hal::digital::v2::OutputPin;
 // use stm32f4xx_hal::prelude::*;
@@ -15,84 +13,21 @@ use rtt_target::rprintln;
 //     spi_emu: SPI,
 // }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
-pub enum Register {
-    ProductId = 0x00,
-    RevisionId = 0x01,
-    Motion = 0x02,
-    DeltaXL = 0x03,
-    DeltaXH = 0x04,
-    DeltaYL = 0x05,
-    DeltaYH = 0x06,
-    SQUAL = 0x07,
-    RawDataSum = 0x08,
-    MaximumRawdata = 0x09,
-    MinimumRawdata = 0x0A,
-    ShutterLower = 0x0B,
-    ShutterUpper = 0x0C,
-    RippleControl = 0x0D,
-    ResolutionL = 0x0E,
-    ResolutionH = 0x0F,
-    Config2 = 0x10,
-    AngleTune = 0x11,
-    FrameCapture = 0x12,
-    SROMEnable = 0x13,
-    RunDownshift = 0x14,
-    Rest1RateLower = 0x15,
-    Rest1RateUpper = 0x16,
-    Rest1Downshift = 0x17,
-    Rest2RateLower = 0x18,
-    Rest2RateUpper = 0x19,
-    Rest2Downshift = 0x1A,
-    Rest3RateLower = 0x1B,
-    Rest3RateUpper = 0x1C,
-    Observation = 0x24,
-    DataOutLower = 0x25,
-    DataOutUpper = 0x26,
-    RawDataDump = 0x29,
-    SROMId = 0x2A,
-    MinSQRun = 0x2B,
-    RawDataThreshold = 0x2C,
-    Control2 = 0x2D,
-    Config5L = 0x2E,
-    Config5H = 0x2F,
-    PowerUpReset = 0x3A,
-    Shutdown = 0x3B,
-    InverseProductID = 0x3F,
-    LiftCutoffTune3 = 0x41,
-    AngleSnap = 0x42,
-    LiftCutoffTune1 = 0x4A,
-    MotionBurst = 0x50,
-    LiftCutoffTune1Timeout = 0x58,
-    LiftCutoffTune1MinLength = 0x5A,
-    SROMLoadBurst = 0x62,
-    LiftConfig = 0x63,
-    RawDataBurst = 0x64,
-    LiftCutoffTune2 = 0x65,
-    LiftCutoffTune2Timeout = 0x71,
-    LiftCutoffTune2MinLength = 0x72,
-    PWMPeriodCnt = 0x73,
-    PWMWidthCnt = 0x74,
-}
-
-impl Register {
-    fn addr(self) -> u8 {
-        self as u8
-    }
-}
+pub use crate::pmw3389::Register;
 
-pub struct Pmw3389e<SPI, E>
+pub struct Pmw3389e<SPI, D, E>
 where
     SPI: Transfer<u8, Error = E> + OutputPin,
+    D: DelayUs<u32> + DelayMs<u32>,
 {
     spi: SPI,
-    delay: DwtDelay,
+    delay: D,
 }
 
-impl<SPI, E> Pmw3389e<SPI, E>
+impl<SPI, D, E> Pmw3389e<SPI, D, E>
 where
     SPI: Transfer<u8, Error = E> + OutputPin,
+    D: DelayUs<u32> + DelayMs<u32>,
 {
     fn com_begin(&mut self) {
         self.spi.set_low().ok();
@@ -103,7 +38,7 @@ where
     }
 
     /// Creates a new driver from a SPI peripheral and a NCS pin
-    pub fn new(spi: SPI, delay: DwtDelay) -> Result<Self, E> {
+    pub fn new(spi: SPI, delay: D) -> Result<Self, E> {
         let mut pmw3389 = Pmw3389e { spi, delay };
 
         rprintln!("pmw3389 - new");
@@ -0,0 +1,121 @@
+//! Lossless report queue for the HID mouse
+//!
+//! Accumulates sensor deltas (and scroll) between USB IN transactions.
+//! Counts are only removed once a report is accepted by the endpoint, a
+//! movement beyond the range of a report (+-32767, or +-127 in boot
+//! protocol) is split over consecutive reports. Nothing is dropped or
+//! wrapped, so fast flicks reach the host exactly.
+
+use usb_device::{bus::UsbBus, Result};
+
+use crate::hid::{MouseClass, MouseReport, Protocol, XY_MAX};
+
+// Range of X/Y in the boot report
+const BOOT_XY_MAX: i32 = 127;
+// Range of wheel and pan
+const SCROLL_MAX: i32 = 127;
+
+#[derive(Default)]
+pub struct ReportQueue {
+    buttons: u8,
+    // the buttons in the last report sent
+    sent_buttons: u8,
+    // counts not yet reported
+    x: i32,
+    y: i32,
+    wheel: i32,
+    pan: i32,
+}
+
+impl ReportQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the button state, bit 0 left, 1 right, 2 middle, 3 back, 4 forward
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    /// Adds a motion sample
+    pub fn add_motion(&mut self, dx: i32, dy: i32) {
+        self.x = self.x.saturating_add(dx);
+        self.y = self.y.saturating_add(dy);
+    }
+
+    /// Adds wheel and pan steps
+    pub fn add_scroll(&mut self, wheel: i32, pan: i32) {
+        self.wheel = self.wheel.saturating_add(wheel);
+        self.pan = self.pan.saturating_add(pan);
+    }
+
+    /// Counts not yet reported (x, y)
+    pub fn motion(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// There is a button change or motion to report
+    pub fn is_pending(&self) -> bool {
+        self.buttons != self.sent_buttons
+            || self.x != 0
+            || self.y != 0
+            || self.wheel != 0
+            || self.pan != 0
+    }
+
+    /// The next report for `protocol`, `None` if nothing is pending
+    pub fn peek(&self, protocol: Protocol) -> Option<MouseReport> {
+        if !self.is_pending() {
+            return None;
+        }
+        let xy_max = match protocol {
+            Protocol::Boot => BOOT_XY_MAX,
+            Protocol::Report => XY_MAX as i32,
+        };
+        Some(MouseReport {
+            buttons: self.buttons,
+            x: clamp(self.x, xy_max) as i16,
+            y: clamp(self.y, xy_max) as i16,
+            wheel: clamp(self.wheel, SCROLL_MAX) as i8,
+            pan: clamp(self.pan, SCROLL_MAX) as i8,
+        })
+    }
+
+    /// Removes the counts of a report accepted by the endpoint
+    ///
+    /// The boot report has no wheel and pan, these are dropped.
+    pub fn commit(&mut self, report: &MouseReport, protocol: Protocol) {
+        self.sent_buttons = report.buttons;
+        self.x -= report.x as i32;
+        self.y -= report.y as i32;
+        match protocol {
+            Protocol::Boot => {
+                self.wheel = 0;
+                self.pan = 0;
+            }
+            Protocol::Report => {
+                self.wheel -= report.wheel as i32;
+                self.pan -= report.pan as i32;
+            }
+        }
+    }
+
+    /// Sends the next report if the endpoint is free, `Ok(true)` if sent
+    ///
+    /// On `WouldBlock` the counts are kept for the next attempt.
+    pub fn flush<B: UsbBus>(&mut self, hid: &mut MouseClass<'_, B>) -> Result<bool> {
+        let protocol = hid.protocol();
+        match self.peek(protocol) {
+            Some(report) => {
+                hid.push(&report)?;
+                self.commit(&report, protocol);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+fn clamp(v: i32, max: i32) -> i32 {
+    v.max(-max).min(max)
+}
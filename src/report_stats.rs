@@ -0,0 +1,93 @@
+//! Report rate statistics
+//!
+//! Measures the rate the host actually collects mouse reports, against the
+//! polling interval (bInterval) of the endpoint. Time is divided into
+//! frames of one polling interval, a frame without a report is
+//!
+//! - missed, if there was motion or a button change waiting (the endpoint
+//!   was still busy, or the report came too late), or
+//! - empty, if there was nothing to report.
+//!
+//! Fed by the application with the outcome of `ReportQueue::flush`
+//! (`sent`) and its time base (`tick`), read back by the diagnostics of
+//! `config_proto`.
+
+// Window of the report rate, in ms
+const WINDOW_MS: u32 = 1000;
+
+pub struct ReportStats {
+    interval_ms: u32,
+    // time into the current frame, and if a report was sent in it
+    frame_ms: u32,
+    frame_sent: bool,
+    // time into the current window, and the reports sent in it
+    window_ms: u32,
+    window_reports: u32,
+    // reports in the last full window
+    rate: u16,
+    missed: u32,
+    empty: u32,
+}
+
+impl ReportStats {
+    /// Statistics for the polling interval `interval_ms`
+    pub fn new(interval_ms: u8) -> Self {
+        ReportStats {
+            interval_ms: interval_ms.max(1) as u32,
+            frame_ms: 0,
+            frame_sent: false,
+            window_ms: 0,
+            window_reports: 0,
+            rate: 0,
+            missed: 0,
+            empty: 0,
+        }
+    }
+
+    /// A report was accepted by the endpoint
+    pub fn sent(&mut self) {
+        self.frame_sent = true;
+        self.window_reports = self.window_reports.saturating_add(1);
+    }
+
+    /// Advances the time by `ms`, `pending` if there is something waiting
+    /// to be reported
+    pub fn tick(&mut self, ms: u32, pending: bool) {
+        for _ in 0..ms {
+            self.frame_ms += 1;
+            if self.frame_ms >= self.interval_ms {
+                if !self.frame_sent {
+                    if pending {
+                        self.missed = self.missed.saturating_add(1);
+                    } else {
+                        self.empty = self.empty.saturating_add(1);
+                    }
+                }
+                self.frame_ms = 0;
+                self.frame_sent = false;
+            }
+
+            self.window_ms += 1;
+            if self.window_ms >= WINDOW_MS {
+                self.rate = self.window_reports.min(u16::MAX as u32) as u16;
+                self.window_ms = 0;
+                self.window_reports = 0;
+            }
+        }
+    }
+
+    /// Reports per second, over the last second
+    pub fn rate(&self) -> u16 {
+        self.rate
+    }
+
+    /// Frames without a report while there was something to report
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+
+    /// Frames without a report, nothing to report
+    pub fn empty(&self) -> u32 {
+        self.empty
+    }
+}
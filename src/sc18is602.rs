@@ -0,0 +1,769 @@
+//! SC18IS602 I2C to SPI bridge driver
+//!
+//! Provides the SPI (`Transfer`/`Write`) and chip select (`OutputPin`)
+//! traits of embedded-hal on top of an I2C bus, so any SPI device driver
+//! generic over these traits can be used behind the bridge.
+//!
+//! The bridge has four slave select lines (SS0..SS3). `split` gives one
+//! handle per line, each turned into an independent SPI device sharing
+//! the I2C bus. A device either has its SS line managed by the bridge
+//! (asserted for the duration of each transfer), or driven as a GPIO
+//! through the `OutputPin` trait of the device.
+//!
+//! Lines not used for SPI devices can be turned into general purpose
+//! `OutputPin`/`InputPin`s (e.g., for a reset line or status LEDs).
+//!
+//! The bridge buffers a transfer in its 200 byte data buffer. For GPIO
+//! managed devices larger transfers are split into consecutive transfers
+//! of (at most) 200 bytes, while the bridge would release a hardware
+//! managed SS line in between, so these are rejected (`Error::Oversize`).
+//! Each transfer is awaited before the data is read back, either by
+//! polling the bridge or through its INT output (see `Completion`), with
+//! the expected duration computed from the configured `Speed`.
+//!
+//! All I2C errors (e.g., a NACK) are reported, `E` being the error type
+//! of the I2C bus.
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+
+use embedded_hal::{
+    blocking::{
+        delay::DelayUs,
+        i2c,
+        spi::{Transfer, Write},
+    },
+    digital::v2::{InputPin, OutputPin},
+    spi::Mode,
+};
+
+use rtt_target::rprintln;
+
+/// Size of the data buffer of the bridge
+pub const BUFFER_SIZE: usize = 200;
+
+pub enum Function {
+    SpiReadWrite = 0x00, // 0F..01, where lowest 4 bits are the CSs
+    SpiConfigure = 0xF0,
+    ClearInterrupt = 0xF1,
+    IdleMode = 0xF2,
+    GpioWrite = 0xF4,
+    GpioRead = 0xF5,
+    GpioEnable = 0xF6,
+    GpioConfigure = 0xF7,
+}
+
+impl Function {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Speed {
+    Speed1843kHz = 0b00,
+    Speed461kHz = 0b01,
+    Speed115kHz = 0b10,
+    Speed58kHz = 0b11,
+}
+
+impl Speed {
+    /// The SPI clock rate in Hz
+    pub fn hz(self) -> u32 {
+        match self {
+            Speed::Speed1843kHz => 1_843_000,
+            Speed::Speed461kHz => 461_000,
+            Speed::Speed115kHz => 115_000,
+            Speed::Speed58kHz => 58_000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Order {
+    MsbFirst = 0b0,
+    MsbLast = 0b1,
+}
+
+/// Configuration of a SS line used as GPIO
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GpioMode {
+    QuasiBiDirectional = 0b00,
+    PushPull = 0b01,
+    InputOnly = 0b10,
+    OpenDrain = 0b11,
+}
+
+impl GpioMode {
+    fn val(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Management of the SS line of a SPI device
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SsMode {
+    /// asserted by the bridge during each transfer
+    Hardware,
+    /// driven as a GPIO, through `OutputPin`
+    Gpio,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error<E> {
+    /// I2C bus error during a transfer or GPIO access (e.g., a NACK)
+    I2c(E),
+    /// I2C bus error while writing the configuration of the bridge
+    Config(E),
+    /// Transfer beyond the data buffer, on a hardware managed SS line
+    Oversize,
+    /// Operation not supported in the current mode of the line
+    NotConfigured,
+    /// GPIO managed transfers need a spare (unused) SS line to select
+    NoSpareSs,
+    /// The SPI transfer did not complete in time
+    Timeout,
+}
+
+/// Detection of a completed SPI transfer
+///
+/// `wait` is called after the data buffer is written (starting the
+/// transfer), with the expected duration `us` of the transfer.
+pub trait Completion {
+    fn wait<I2C: i2c::Write>(
+        &mut self,
+        i2c: &mut I2C,
+        addr: u8,
+        us: u32,
+    ) -> Result<(), Error<I2C::Error>>;
+
+    /// Waits `us`, e.g., for a slave select line to settle
+    fn delay_us(&mut self, us: u32);
+}
+
+/// Setup time of a GPIO managed SS line, from asserted to the transfer, in us
+///
+/// The line is driven as the GpioWrite is acknowledged, while the transfer
+/// starts no sooner than its function id is written, tens of us later (at
+/// 400 kHz), so the delay only adds a margin, well beyond the 120 ns
+/// tNCS-SCLK of the PMW3389.
+pub const SS_SETUP_US: u32 = 1;
+
+// Time allowed on top of the expected duration, before giving up, in us
+fn timeout(us: u32) -> u32 {
+    2 * us + 100
+}
+
+// Probes of the bridge within the timeout
+const PROBES: u32 = 16;
+
+/// Completion by polling the bridge
+///
+/// The bridge does not acknowledge its address while the SPI transfer is
+/// ongoing, so after the expected duration we probe until it responds,
+/// at most `PROBES` times spread over the timeout. The time spent in the
+/// probes themselves adds to the timeout.
+pub struct Polling<D> {
+    delay: D,
+}
+
+impl<D: DelayUs<u32>> Polling<D> {
+    pub fn new(delay: D) -> Self {
+        Polling { delay }
+    }
+}
+
+impl<D: DelayUs<u32>> Completion for Polling<D> {
+    fn wait<I2C: i2c::Write>(
+        &mut self,
+        i2c: &mut I2C,
+        addr: u8,
+        us: u32,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.delay.delay_us(us);
+        let step = timeout(us) / PROBES + 1;
+        for _ in 0..PROBES {
+            // a NACK is expected until done
+            if i2c.write(addr, &[]).is_ok() {
+                return Ok(());
+            }
+            self.delay.delay_us(step);
+        }
+        Err(Error::Timeout)
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay.delay_us(us);
+    }
+}
+
+/// Completion signaled on the (active low) INT output of the bridge
+///
+/// The interrupt is cleared by `ClearInterrupt` after each transfer.
+pub struct Interrupt<P, D> {
+    int: P,
+    delay: D,
+}
+
+impl<P: InputPin, D: DelayUs<u32>> Interrupt<P, D> {
+    pub fn new(int: P, delay: D) -> Self {
+        Interrupt { int, delay }
+    }
+}
+
+impl<P: InputPin, D: DelayUs<u32>> Completion for Interrupt<P, D> {
+    fn wait<I2C: i2c::Write>(
+        &mut self,
+        i2c: &mut I2C,
+        addr: u8,
+        us: u32,
+    ) -> Result<(), Error<I2C::Error>> {
+        let mut waited = 0;
+        while self.int.is_high().unwrap_or(true) {
+            if waited > timeout(us) {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_us(1);
+            waited += 1;
+        }
+        i2c.write(addr, &[ClearInterrupt.id()]).map_err(Error::I2c)
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay.delay_us(us);
+    }
+}
+
+/// The state shared by the devices of a bridge
+pub struct Bus<I2C, C> {
+    i2c: I2C,
+    completion: C,
+    // SPI clock rate
+    hz: u32,
+    // a backing buffer for shadowing SPI transfers (function id + data)
+    buff: [u8; BUFFER_SIZE + 1],
+    // shadows of the GpioEnable, GpioConfigure and GpioWrite registers
+    gpio_enable: u8,
+    gpio_config: u8,
+    gpio_output: u8,
+    // SS lines used by hardware managed devices
+    hw_ss: u8,
+}
+
+/// Mutual exclusion of the bus, shared by the devices of a bridge
+pub trait BusMutex<T> {
+    fn create(v: T) -> Self;
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R;
+}
+
+/// Allows the devices to be used from tasks (interrupt handlers) of
+/// priority up to `CEILING`, e.g., as resources of RTIC tasks
+///
+/// As RTIC's `lock`, the lock raises BASEPRI to the (logical) priority
+/// `CEILING` (1..=16), so interrupts of higher priority (e.g., USB) are
+/// served during a transfer. A task above the ceiling locking the bus
+/// while it is locked panics (already borrowed).
+pub struct Ceiling<T, const CEILING: u8> {
+    v: RefCell<T>,
+}
+
+// Priority bits of the NVIC (STM32F4)
+const NVIC_PRIO_BITS: u8 = 4;
+
+// A task preempting a lock (above the ceiling) runs to completion before
+// the lock is resumed (single core), so the borrow flag is never torn
+unsafe impl<T: Send, const CEILING: u8> Sync for Ceiling<T, CEILING> {}
+
+impl<T, const CEILING: u8> BusMutex<T> for Ceiling<T, CEILING> {
+    fn create(v: T) -> Self {
+        Ceiling { v: RefCell::new(v) }
+    }
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        use cortex_m::register::{basepri, basepri_max};
+
+        if CEILING >= 1 << NVIC_PRIO_BITS {
+            // BASEPRI cannot mask the highest priority
+            return cortex_m::interrupt::free(|_| f(&mut self.v.borrow_mut()));
+        }
+
+        let current = basepri::read();
+        basepri_max::write(((1 << NVIC_PRIO_BITS) - CEILING) << (8 - NVIC_PRIO_BITS));
+        let r = f(&mut self.v.borrow_mut());
+        unsafe { basepri::write(current) };
+        r
+    }
+}
+
+/// Allows the devices to be used from any task, masking all interrupts
+/// while the bus is locked (including the wait for a transfer)
+impl<T> BusMutex<T> for cortex_m::interrupt::Mutex<RefCell<T>> {
+    fn create(v: T) -> Self {
+        cortex_m::interrupt::Mutex::new(RefCell::new(v))
+    }
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        cortex_m::interrupt::free(|cs| f(&mut self.borrow(cs).borrow_mut()))
+    }
+}
+
+/// Devices used from a single context only (e.g., on the host)
+impl<T> BusMutex<T> for RefCell<T> {
+    fn create(v: T) -> Self {
+        RefCell::new(v)
+    }
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.borrow_mut())
+    }
+}
+
+/// The bridge, by default shared by tasks of priority 1 (see `Ceiling`)
+pub struct SH18IS602<I2C, C, M = Ceiling<Bus<I2C, C>, 1>> {
+    addr: u8,
+    bus: M,
+    // the bus types are owned by `bus`, keeps the bridge `Sync`
+    _bus: PhantomData<fn() -> (I2C, C)>,
+}
+
+use Function::*;
+
+impl<I2C, C, E> SH18IS602<I2C, C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+{
+    /// Creates a new driver, `addr` being the A2:A0 pin setting
+    ///
+    /// All SS lines are initially managed by the bridge.
+    pub fn new(
+        i2c: I2C,
+        completion: C,
+        addr: u8,
+        order: Order,
+        mode: Mode,
+        speed: Speed,
+    ) -> Result<Self, Error<E>> {
+        Self::with_mutex(i2c, completion, addr, order, mode, speed)
+    }
+}
+
+impl<I2C, C, M, E> SH18IS602<I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    /// Creates a new driver, using the mutex `M` for sharing the bus
+    pub fn with_mutex(
+        i2c: I2C,
+        completion: C,
+        addr: u8,
+        order: Order,
+        mode: Mode,
+        speed: Speed,
+    ) -> Result<Self, Error<E>> {
+        // 7-bit address 0101 A2 A1 A0
+        let addr = 0x28 | (addr & 0x7);
+
+        // configure
+        // 7:6 -     reserved
+        // 5   ORDER logic 0, the MSB of the data word is transmitted first.
+        //           logic 1, the LSB of the data word is transmitted first.
+        // 4   -     reserved
+        // 3:3 M1:M0 Mode selection
+        //           00 - SPICLK LOW when idle; data clocked in on leading edge (CPOL = 0, CPHA = 0)
+        //           01 - SPICLK LOW when idle; data clocked in on trailing edge (CPOL = 0, CPHA = 1)
+        //           10 - SPICLK HIGH when idle; data clocked in on trailing edge (CPOL = 1, CPHA = 0)
+        //           11 - SPICLK HIGH when idle; data clocked in on leading edge (CPOL = 1, CPHA = 1)
+        // 1:0 F1:F0 SPI clock rate
+        //           00 - 1843 kHz
+        //           01 - 461 kHz
+        //           10 - 115 kHz
+        //           11 - 58 kHz
+
+        let hz = speed.hz();
+        let cfg = (order as u8) << 5
+            | (mode.polarity as u8) << 3
+            | (mode.phase as u8) << 2
+            | speed as u8;
+
+        let mut bus = Bus {
+            i2c,
+            completion,
+            hz,
+            buff: [0; BUFFER_SIZE + 1],
+            gpio_enable: 0,
+            gpio_config: 0,
+            gpio_output: 0,
+            hw_ss: 0,
+        };
+
+        bus.i2c
+            .write(addr, &[SpiConfigure.id(), cfg])
+            .map_err(Error::Config)?;
+        bus.write_gpio_enable(addr)?;
+
+        Ok(SH18IS602 {
+            addr,
+            bus: M::create(bus),
+            _bus: PhantomData,
+        })
+    }
+
+    /// Splits the bridge into its four slave select lines
+    ///
+    /// The lines borrow the bridge. To use them as resources of different
+    /// tasks, split a `&'static mut` bridge (e.g., kept in a `static mut`
+    /// of RTIC's `init`), see `examples/rtt_rtic_i2c.rs`.
+    pub fn split(&mut self) -> Parts<'_, I2C, C, M> {
+        let bridge = &*self;
+        Parts {
+            ss0: Ss { bridge, n: 0 },
+            ss1: Ss { bridge, n: 1 },
+            ss2: Ss { bridge, n: 2 },
+            ss3: Ss { bridge, n: 3 },
+        }
+    }
+
+    /// Puts the bridge in its low power (idle) mode
+    ///
+    /// The bridge wakes up on its next I2C access, e.g., the next transfer.
+    /// Also available through the devices of a split bridge.
+    pub fn idle(&self) -> Result<(), Error<E>> {
+        let addr = self.addr;
+        self.bus
+            .lock(|bus| bus.i2c.write(addr, &[IdleMode.id()]))
+            .map_err(Error::I2c)
+    }
+}
+
+impl<I2C, C, E> Bus<I2C, C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+{
+    fn write_gpio_enable(&mut self, addr: u8) -> Result<(), Error<E>> {
+        self.i2c
+            .write(addr, &[GpioEnable.id(), self.gpio_enable])
+            .map_err(Error::Config)
+    }
+
+    fn write_gpio_config(&mut self, addr: u8) -> Result<(), Error<E>> {
+        self.i2c
+            .write(addr, &[GpioConfigure.id(), self.gpio_config])
+            .map_err(Error::Config)
+    }
+
+    fn write_gpio_output(&mut self, addr: u8) -> Result<(), Error<E>> {
+        self.i2c
+            .write(addr, &[GpioWrite.id(), self.gpio_output])
+            .map_err(Error::I2c)
+    }
+
+    // Enables SSn as GPIO in the given mode
+    fn configure_gpio(&mut self, addr: u8, n: u8, mode: GpioMode) -> Result<(), Error<E>> {
+        self.gpio_enable |= 1 << n;
+        self.write_gpio_enable(addr)?;
+
+        self.gpio_config &= !(0b11 << (2 * n));
+        self.gpio_config |= mode.val() << (2 * n);
+        self.write_gpio_config(addr)
+    }
+
+    fn set_gpio_output(&mut self, addr: u8, n: u8, high: bool) -> Result<(), Error<E>> {
+        if high {
+            self.gpio_output |= 1 << n;
+        } else {
+            self.gpio_output &= !(1 << n);
+        }
+        self.write_gpio_output(addr)
+    }
+
+    // Reads the pin states of SS0..SS3
+    fn read_gpio(&mut self, addr: u8) -> Result<u8, Error<E>> {
+        let mut buff = [0];
+        self.i2c.write(addr, &[GpioRead.id()]).map_err(Error::I2c)?;
+        self.i2c.read(addr, &mut buff).map_err(Error::I2c)?;
+        Ok(buff[0])
+    }
+
+    // The SpiReadWrite function id, selecting the SS line of the transfer
+    fn ss_function(&self, n: u8, mode: SsMode, len: usize) -> Result<u8, Error<E>> {
+        match mode {
+            SsMode::Hardware if len > BUFFER_SIZE => Err(Error::Oversize),
+            SsMode::Hardware => Ok(SpiReadWrite.id() | 1 << n),
+            SsMode::Gpio => {
+                // The SPI transfer is disabled if the selected SSx is
+                // configured as GPIO, so we select a spare SS line
+                // instead, while SSn is driven through `OutputPin`
+                let spare = !(self.gpio_enable | self.hw_ss) & 0x0f;
+                if spare == 0 {
+                    Err(Error::NoSpareSs)
+                } else {
+                    Ok(SpiReadWrite.id() | 1 << spare.trailing_zeros())
+                }
+            }
+        }
+    }
+
+    // Expected duration of a SPI transfer of `len` bytes, in us (at least)
+    fn transfer_us(&self, len: usize) -> u32 {
+        len as u32 * 8 * 1_000_000 / self.hz + 1
+    }
+
+    // Writes (at most BUFFER_SIZE) bytes to the data buffer, starting the
+    // transfer, and waits for it to complete
+    fn write_chunk(&mut self, addr: u8, function: u8, words: &[u8]) -> Result<(), Error<E>> {
+        self.buff[0] = function;
+        self.buff[1..words.len() + 1].copy_from_slice(words);
+        // perform the transaction on words.len() + 1 bytes
+        // the actual SPI transfer should be words.len()
+        self.i2c
+            .write(addr, &self.buff[0..words.len() + 1])
+            .map_err(Error::I2c)?;
+
+        let us = self.transfer_us(words.len());
+        self.completion.wait(&mut self.i2c, addr, us)
+    }
+}
+
+/// The slave select lines of a bridge
+pub struct Parts<'a, I2C, C, M> {
+    pub ss0: Ss<'a, I2C, C, M>,
+    pub ss1: Ss<'a, I2C, C, M>,
+    pub ss2: Ss<'a, I2C, C, M>,
+    pub ss3: Ss<'a, I2C, C, M>,
+}
+
+/// An (unused) slave select line
+pub struct Ss<'a, I2C, C, M> {
+    bridge: &'a SH18IS602<I2C, C, M>,
+    n: u8,
+}
+
+impl<'a, I2C, C, M, E> Ss<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    /// Turns the line into the slave select of a SPI device
+    pub fn into_spi(self, mode: SsMode) -> Result<SpiDevice<'a, I2C, C, M>, Error<E>> {
+        let (addr, n) = (self.bridge.addr, self.n);
+        self.bridge.bus.lock(|bus| match mode {
+            SsMode::Hardware => {
+                rprintln!("SS{} managed by HW", n);
+                bus.hw_ss |= 1 << n;
+                bus.gpio_enable &= !(1 << n);
+                bus.write_gpio_enable(addr)
+            }
+            SsMode::Gpio => {
+                rprintln!("SS{} as GPIO", n);
+                // Set high out of transaction (idle), before driven, so the
+                // device isn't selected meanwhile
+                bus.set_gpio_output(addr, n, true)?;
+                bus.configure_gpio(addr, n, GpioMode::PushPull)
+            }
+        })?;
+
+        Ok(SpiDevice {
+            bridge: self.bridge,
+            n,
+            mode,
+        })
+    }
+
+    /// Turns the line into a general purpose pin
+    ///
+    /// Notice, a GPIO managed SPI device needs one spare line, i.e.,
+    /// not all four lines can be GPIOs in that case.
+    pub fn into_gpio(self, mode: GpioMode) -> Result<GpioPin<'a, I2C, C, M>, Error<E>> {
+        let (addr, n) = (self.bridge.addr, self.n);
+        self.bridge
+            .bus
+            .lock(|bus| bus.configure_gpio(addr, n, mode))?;
+
+        Ok(GpioPin {
+            bridge: self.bridge,
+            n,
+            mode,
+        })
+    }
+}
+
+/// A SS line used as general purpose pin
+pub struct GpioPin<'a, I2C, C, M> {
+    bridge: &'a SH18IS602<I2C, C, M>,
+    n: u8,
+    mode: GpioMode,
+}
+
+impl<'a, I2C, C, M, E> GpioPin<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    pub fn mode(&self) -> GpioMode {
+        self.mode
+    }
+
+    /// Reconfigures the pin
+    pub fn set_mode(&mut self, mode: GpioMode) -> Result<(), Error<E>> {
+        let (addr, n) = (self.bridge.addr, self.n);
+        self.bridge
+            .bus
+            .lock(|bus| bus.configure_gpio(addr, n, mode))?;
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn set(&mut self, high: bool) -> Result<(), Error<E>> {
+        if self.mode == GpioMode::InputOnly {
+            return Err(Error::NotConfigured);
+        }
+
+        let (addr, n) = (self.bridge.addr, self.n);
+        self.bridge
+            .bus
+            .lock(|bus| bus.set_gpio_output(addr, n, high))
+    }
+
+    fn get(&self) -> Result<bool, Error<E>> {
+        let (addr, n) = (self.bridge.addr, self.n);
+        let pins = self.bridge.bus.lock(|bus| bus.read_gpio(addr))?;
+        Ok(pins & 1 << n != 0)
+    }
+}
+
+impl<'a, I2C, C, M, E> OutputPin for GpioPin<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    type Error = Error<E>;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true)
+    }
+}
+
+impl<'a, I2C, C, M, E> InputPin for GpioPin<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    type Error = Error<E>;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.get()
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.get().map(|high| !high)
+    }
+}
+
+/// A SPI device on one of the slave select lines of the bridge
+pub struct SpiDevice<'a, I2C, C, M> {
+    bridge: &'a SH18IS602<I2C, C, M>,
+    n: u8,
+    mode: SsMode,
+}
+
+impl<'a, I2C, C, M, E> SpiDevice<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    fn set_ss(&mut self, high: bool) -> Result<(), Error<E>> {
+        if self.mode != SsMode::Gpio {
+            return Err(Error::NotConfigured);
+        }
+
+        let (addr, n) = (self.bridge.addr, self.n);
+        self.bridge
+            .bus
+            .lock(|bus| bus.set_gpio_output(addr, n, high))
+    }
+
+    /// Puts the (whole) bridge in its low power (idle) mode
+    pub fn idle(&self) -> Result<(), Error<E>> {
+        self.bridge.idle()
+    }
+}
+
+impl<'a, I2C, C, M, E> Transfer<u8> for SpiDevice<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    type Error = Error<E>;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        let (addr, n, mode) = (self.bridge.addr, self.n, self.mode);
+        self.bridge.bus.lock(|bus| {
+            let function = bus.ss_function(n, mode, words.len())?;
+            for chunk in words.chunks_mut(BUFFER_SIZE) {
+                bus.write_chunk(addr, function, chunk)?;
+                // read back the data received during the transfer
+                bus.i2c.read(addr, chunk).map_err(Error::I2c)?;
+            }
+            Ok(())
+        })?;
+
+        Ok(words)
+    }
+}
+
+impl<'a, I2C, C, M, E> Write<u8> for SpiDevice<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    type Error = Error<E>;
+
+    // For improved performance use write if result is not needed
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        let (addr, n, mode) = (self.bridge.addr, self.n, self.mode);
+        self.bridge.bus.lock(|bus| {
+            let function = bus.ss_function(n, mode, words.len())?;
+            for chunk in words.chunks(BUFFER_SIZE) {
+                bus.write_chunk(addr, function, chunk)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<'a, I2C, C, M, E> OutputPin for SpiDevice<'a, I2C, C, M>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    C: Completion,
+    M: BusMutex<Bus<I2C, C>>,
+{
+    type Error = Error<E>;
+
+    /// Asserts the SS line, then waits `SS_SETUP_US`
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_ss(false)?;
+        self.bridge
+            .bus
+            .lock(|bus| bus.completion.delay_us(SS_SETUP_US));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_ss(true)
+    }
+}
@@ -0,0 +1,76 @@
+//! Fixed-point motion scaling
+//!
+//! Scales sensor counts by a Q16.16 factor. The fractional part of each
+//! scaled sample is carried over to the next, so the sum of the outputs
+//! always equals the (floored) scaled sum of the inputs, without drift.
+//!
+//! Target independent, so it can be exercised on the host.
+
+/// Fractional bits of the factor
+pub const FRAC_BITS: u32 = 16;
+
+/// The factor 1.0
+pub const ONE: u32 = 1 << FRAC_BITS;
+
+pub struct MotionScaler {
+    // Q16.16 multiplier
+    factor: u32,
+    // fractional counts carried to the next sample, in 0..ONE
+    rem_x: i64,
+    rem_y: i64,
+}
+
+impl MotionScaler {
+    /// Creates a scaler from a Q16.16 `factor`, e.g., `ONE` for 1.0
+    pub const fn new(factor: u32) -> Self {
+        MotionScaler {
+            factor,
+            rem_x: 0,
+            rem_y: 0,
+        }
+    }
+
+    /// Creates a scaler with the factor `num / den`, saturated to the
+    /// largest factor
+    pub fn from_ratio(num: u32, den: u32) -> Self {
+        let factor = ((num as u64) << FRAC_BITS) / den.max(1) as u64;
+        Self::new(factor.min(u32::MAX as u64) as u32)
+    }
+
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+
+    /// Changes the factor, pending fractions are kept
+    pub fn set_factor(&mut self, factor: u32) {
+        self.factor = factor;
+    }
+
+    /// Drops the pending fractional counts
+    pub fn reset(&mut self) {
+        self.rem_x = 0;
+        self.rem_y = 0;
+    }
+
+    /// Scales a motion sample (dx, dy) into whole counts
+    pub fn scale(&mut self, dx: i16, dy: i16) -> (i32, i32) {
+        (
+            scale_axis(dx, self.factor, &mut self.rem_x),
+            scale_axis(dy, self.factor, &mut self.rem_y),
+        )
+    }
+}
+
+impl Default for MotionScaler {
+    fn default() -> Self {
+        Self::new(ONE)
+    }
+}
+
+fn scale_axis(delta: i16, factor: u32, rem: &mut i64) -> i32 {
+    let total = delta as i64 * factor as i64 + *rem;
+    // arithmetic shift, rounds towards negative infinity
+    let counts = total >> FRAC_BITS;
+    *rem = total - (counts << FRAC_BITS);
+    counts as i32
+}
@@ -0,0 +1,92 @@
+//! Wheel and pan input
+//!
+//! Collects scroll input in high-resolution units, 1/`HIRES_MULTIPLIER` of a
+//! detent, from a quadrature encoder (`add_detents`, `add_hires`) or from
+//! sensor motion while a scroll button is held (`add_motion`).
+//!
+//! `take` converts to the units of the report, as of the resolution
+//! multipliers enabled by the host (`MouseClass::resolution_multiplier`).
+//! The remainder is kept, so a host without high-resolution scrolling still
+//! gets every whole detent.
+
+/// Wheel and pan units per detent with the resolution multiplier enabled
+pub const HIRES_MULTIPLIER: u8 = 8;
+
+const HIRES: i32 = HIRES_MULTIPLIER as i32;
+
+pub struct Scroll {
+    // sensor counts per detent
+    counts_per_detent: i32,
+    // sensor counts (in high-resolution units) not yet converted (x, y)
+    motion_x: i32,
+    motion_y: i32,
+    // high-resolution units not yet taken
+    wheel: i32,
+    pan: i32,
+}
+
+impl Scroll {
+    /// Sensor scrolling moves one detent per `counts_per_detent` counts
+    pub fn new(counts_per_detent: u16) -> Self {
+        Scroll {
+            counts_per_detent: counts_per_detent.max(1) as i32,
+            motion_x: 0,
+            motion_y: 0,
+            wheel: 0,
+            pan: 0,
+        }
+    }
+
+    pub fn set_counts_per_detent(&mut self, counts_per_detent: u16) {
+        self.counts_per_detent = counts_per_detent.max(1) as i32;
+    }
+
+    /// Adds whole detents, wheel positive up (away from the user), pan
+    /// positive right
+    pub fn add_detents(&mut self, wheel: i32, pan: i32) {
+        self.add_hires(wheel.saturating_mul(HIRES), pan.saturating_mul(HIRES));
+    }
+
+    /// Adds high-resolution units, 1/`HIRES_MULTIPLIER` detent each
+    pub fn add_hires(&mut self, wheel: i32, pan: i32) {
+        self.wheel = self.wheel.saturating_add(wheel);
+        self.pan = self.pan.saturating_add(pan);
+    }
+
+    /// Adds sensor motion (as reported, y positive towards the user),
+    /// moving forward scrolls up and sideways pans
+    pub fn add_motion(&mut self, dx: i32, dy: i32) {
+        self.motion_x = self.motion_x.saturating_add(dx.saturating_mul(HIRES));
+        self.motion_y = self.motion_y.saturating_add(dy.saturating_mul(HIRES));
+        // the remainder (towards zero) is carried to the next sample
+        let pan = self.motion_x / self.counts_per_detent;
+        let wheel = -(self.motion_y / self.counts_per_detent);
+        self.motion_x -= pan * self.counts_per_detent;
+        self.motion_y += wheel * self.counts_per_detent;
+        self.add_hires(wheel, pan);
+    }
+
+    /// Drops the sensor remainder, e.g., when the scroll button is released
+    pub fn reset_motion(&mut self) {
+        self.motion_x = 0;
+        self.motion_y = 0;
+    }
+
+    /// Takes the (wheel, pan) in report units for the resolution
+    /// multipliers `multiplier`, 1 or `HIRES_MULTIPLIER` each, to be queued
+    /// by `ReportQueue::add_scroll`
+    pub fn take(&mut self, multiplier: (u8, u8)) -> (i32, i32) {
+        (
+            take_axis(&mut self.wheel, multiplier.0),
+            take_axis(&mut self.pan, multiplier.1),
+        )
+    }
+}
+
+// Whole report units of `hires`, the remainder is kept
+fn take_axis(hires: &mut i32, multiplier: u8) -> i32 {
+    let step = if multiplier >= HIRES_MULTIPLIER { 1 } else { HIRES };
+    let n = *hires / step;
+    *hires -= n * step;
+    n
+}
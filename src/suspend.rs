@@ -0,0 +1,127 @@
+//! USB suspend and remote wakeup
+//!
+//! Follows the bus state (`UsbDeviceState::Suspend`) and tells the
+//! application when to power down, when to signal resume to the host
+//! (remote wakeup) and when to power up again.
+//!
+//! On suspend the sensor is shut down, unless the host enabled remote
+//! wakeup (SET_FEATURE DEVICE_REMOTE_WAKEUP), then it is left in its rest
+//! modes to detect motion. Motion or a click while suspended starts the
+//! resume signalling (RWUSIG of OTG_FS_DCTL), held for `RESUME_MS` and
+//! only after the bus has been idle for `HOLDOFF_MS` (USB 2.0, 7.1.7.7).
+//!
+//! While suspended (not signalling) the MCU may be stopped (`may_stop`),
+//! woken periodically to sample for activity, see `low_power`.
+
+/// Resume signalling, 1 to 15 ms
+pub const RESUME_MS: u32 = 5;
+
+/// Idle bus before (or between) resume signalling, at least 5 ms
+pub const HOLDOFF_MS: u32 = 50;
+
+/// What the application is to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The bus is suspended, shut down the sensor if `shutdown`
+    Suspend { shutdown: bool },
+    /// Start the resume signalling
+    SignalResume,
+    /// End the resume signalling
+    EndResume,
+    /// The bus is resumed, power up the sensor if it was shut down
+    Resume { power_up: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Active,
+    // time since suspend (or the last signalling)
+    Suspended { ms: u32 },
+    // time signalling
+    Signalling { ms: u32 },
+}
+
+pub struct Suspend {
+    state: State,
+    // the sensor is shut down
+    shutdown: bool,
+}
+
+impl Suspend {
+    pub fn new() -> Self {
+        Suspend {
+            state: State::Active,
+            shutdown: false,
+        }
+    }
+
+    /// The bus is suspended, or resume is being signalled
+    pub fn is_suspended(&self) -> bool {
+        self.state != State::Active
+    }
+
+    /// Suspended and not signalling resume, so the USB needs no clock
+    pub fn may_stop(&self) -> bool {
+        matches!(self.state, State::Suspended { .. })
+    }
+
+    /// The sensor is shut down
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Advances the time by `ms`, with `suspended` the bus state,
+    /// `wakeup` if remote wakeup is enabled by the host, and `activity` if
+    /// there was motion or a click
+    pub fn update(
+        &mut self,
+        suspended: bool,
+        wakeup: bool,
+        activity: bool,
+        ms: u32,
+    ) -> Option<Event> {
+        match self.state {
+            State::Active if suspended => {
+                self.state = State::Suspended { ms: 0 };
+                self.shutdown = !wakeup;
+                Some(Event::Suspend {
+                    shutdown: self.shutdown,
+                })
+            }
+            State::Active => None,
+            State::Suspended { .. } if !suspended => {
+                self.state = State::Active;
+                let power_up = self.shutdown;
+                self.shutdown = false;
+                Some(Event::Resume { power_up })
+            }
+            State::Suspended { ms: t } => {
+                let t = t.saturating_add(ms);
+                if activity && wakeup && t >= HOLDOFF_MS {
+                    self.state = State::Signalling { ms: 0 };
+                    Some(Event::SignalResume)
+                } else {
+                    self.state = State::Suspended { ms: t };
+                    None
+                }
+            }
+            // ended even if the host already resumes
+            State::Signalling { ms: t } => {
+                let t = t.saturating_add(ms);
+                if t >= RESUME_MS {
+                    self.state = State::Suspended { ms: 0 };
+                    Some(Event::EndResume)
+                } else {
+                    self.state = State::Signalling { ms: t };
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl Default for Suspend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
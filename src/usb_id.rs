@@ -0,0 +1,36 @@
+//! USB identity, VID/PID, bcdDevice, strings and the serial number
+//!
+//! The ids and strings are set at build time (see `build.rs`), the serial
+//! number is the 96-bit unique device ID of the MCU (RM0383, 24.2) in hex,
+//! so units plugged in at the same time can be told apart by the host.
+
+use core::ptr;
+
+include!(concat!(env!("OUT_DIR"), "/usb_id.rs"));
+
+// Unique device ID register, 3 words
+const UID: usize = 0x1fff_7a10;
+
+/// Characters of the serial number
+pub const SERIAL_LEN: usize = 24;
+
+/// Reads the unique device ID
+pub fn uid() -> [u8; 12] {
+    let mut uid = [0; 12];
+    for (i, b) in uid.iter_mut().enumerate() {
+        *b = unsafe { ptr::read_volatile((UID + i) as *const u8) };
+    }
+    uid
+}
+
+/// Formats `uid` as the serial number, upper case hex, into `buf`
+pub fn serial_number<'a>(uid: &[u8; 12], buf: &'a mut [u8; SERIAL_LEN]) -> &'a str {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    for (i, b) in uid.iter().enumerate() {
+        buf[2 * i] = HEX[(b >> 4) as usize];
+        buf[2 * i + 1] = HEX[(b & 0x0f) as usize];
+    }
+    // only hex digits
+    core::str::from_utf8(buf).unwrap()
+}
@@ -0,0 +1,17 @@
+//! USB ids from the build environment
+//!
+//! Shared by `build.rs`, setting the ids of the firmware, and the host
+//! tools, finding the device by the same `USB_VID`/`USB_PID`.
+
+/// Ids without `USB_VID`, `USB_PID` and `USB_BCD_DEVICE`
+pub const VID: u16 = 0xc410;
+pub const PID: u16 = 0x0000;
+pub const BCD_DEVICE: u16 = 0x0010;
+
+/// Parses an id, decimal or 0x hex
+pub fn parse_u16(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
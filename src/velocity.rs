@@ -0,0 +1,163 @@
+//! Velocity and acceleration measurement
+//!
+//! Turns timestamped motion reports (DWT cycle count + counts) into speed
+//! in inches per second (IPS) and acceleration in g, keeping track of the
+//! peak values and of implausible sensor output.
+//!
+//! Used to verify the tracking spec (400+ IPS, 50 g) on a swing rig.
+//! Target independent, so it can be exercised on the host.
+
+use libm::{fabsf, sqrtf};
+
+/// 1 g in inches/s^2
+pub const G: f32 = 386.0886;
+
+/// Default spike limit, in g, well above the 50 g of the spec so the peaks
+/// can exceed it
+pub const SPIKE_ACCEL: f32 = 200.0;
+
+/// Default speed (IPS) before a zero report is flagged
+pub const ZERO_SPEED: f32 = 40.0;
+
+/// Implausible sensor output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Malfunction {
+    /// zero motion reported, while moving at `speed` (IPS)
+    ZeroOutput { speed: f32 },
+    /// acceleration beyond the limit, while still reporting motion
+    Spike { accel: f32 },
+}
+
+pub struct Velocity {
+    // cycle counter frequency, Hz
+    freq: u32,
+    cpi: u16,
+    // limits for malfunction detection
+    max_accel: f32,
+    zero_speed: f32,
+    last: Option<u32>,
+    // previous speed is a valid reference for the acceleration
+    settled: bool,
+    speed: f32,
+    accel: f32,
+    peak_speed: f32,
+    peak_accel: f32,
+    malfunctions: u32,
+}
+
+impl Velocity {
+    /// Creates a new measurement, `freq` being the cycle counter frequency
+    /// (i.e., `clocks.hclk().0`) and `cpi` the sensor resolution
+    pub fn new(freq: u32, cpi: u16) -> Self {
+        Velocity {
+            freq,
+            cpi,
+            max_accel: SPIKE_ACCEL,
+            zero_speed: ZERO_SPEED,
+            last: None,
+            settled: false,
+            speed: 0.0,
+            accel: 0.0,
+            peak_speed: 0.0,
+            peak_accel: 0.0,
+            malfunctions: 0,
+        }
+    }
+
+    /// Sets the limits for malfunction detection
+    ///
+    /// A report is flagged if it implies an acceleration beyond `max_accel`
+    /// (in g), or if it is zero while the previous speed was at least
+    /// `zero_speed` (in IPS), whatever the acceleration (a stall at a slow
+    /// poll rate stays below the limit). Defaults are `SPIKE_ACCEL` and
+    /// `ZERO_SPEED`.
+    pub fn set_limits(&mut self, max_accel: f32, zero_speed: f32) {
+        self.max_accel = max_accel;
+        self.zero_speed = zero_speed;
+    }
+
+    pub fn set_cpi(&mut self, cpi: u16) {
+        self.cpi = cpi;
+    }
+
+    /// Adds a motion report (dx, dy) read at the cycle count `cycles`
+    ///
+    /// The first report only sets the time base, the acceleration is
+    /// computed from the second report on (and after a flagged report).
+    pub fn update(&mut self, cycles: u32, dx: i16, dy: i16) -> Option<Malfunction> {
+        let last = self.last.replace(cycles)?;
+        let dt = cycles.wrapping_sub(last) as f32 / self.freq as f32;
+        if dt <= 0.0 {
+            return None;
+        }
+
+        let (dx, dy) = (dx as f32, dy as f32);
+        let speed = sqrtf(dx * dx + dy * dy) / self.cpi as f32 / dt;
+        let accel = if self.settled {
+            (speed - self.speed) / dt / G
+        } else {
+            0.0
+        };
+
+        let malfunction = self.check(speed, accel);
+        self.speed = speed;
+        self.accel = accel;
+
+        // a flagged report is no reference for the next
+        self.settled = malfunction.is_none();
+
+        match malfunction {
+            Some(_) => self.malfunctions += 1,
+            // the peaks, of every valid report
+            None => {
+                self.peak_speed = self.peak_speed.max(speed);
+                self.peak_accel = self.peak_accel.max(fabsf(accel));
+            }
+        }
+
+        malfunction
+    }
+
+    // Flags an implausible report, at `speed` and `accel`
+    fn check(&self, speed: f32, accel: f32) -> Option<Malfunction> {
+        if speed == 0.0 && self.speed >= self.zero_speed {
+            Some(Malfunction::ZeroOutput { speed: self.speed })
+        } else if speed != 0.0 && fabsf(accel) > self.max_accel {
+            Some(Malfunction::Spike { accel })
+        } else {
+            None
+        }
+    }
+
+    /// Current speed, in IPS
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Current acceleration (along the path), in g
+    pub fn accel(&self) -> f32 {
+        self.accel
+    }
+
+    /// Peak speed, in IPS
+    pub fn peak_speed(&self) -> f32 {
+        self.peak_speed
+    }
+
+    /// Peak (absolute) acceleration, in g
+    pub fn peak_accel(&self) -> f32 {
+        self.peak_accel
+    }
+
+    /// Number of flagged reports
+    pub fn malfunctions(&self) -> u32 {
+        self.malfunctions
+    }
+
+    /// Clears peaks and malfunction count, e.g., between test runs
+    pub fn reset(&mut self) {
+        self.peak_speed = 0.0;
+        self.peak_accel = 0.0;
+        self.malfunctions = 0;
+    }
+}